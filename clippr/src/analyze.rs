@@ -0,0 +1,256 @@
+use crate::Segment;
+use crate::cancel::{self, CancelToken};
+use crate::encode::Crop;
+use crate::error::{Error, Result};
+use crate::strategy::{Axis, DEFAULT_ORDER};
+use std::collections::HashSet;
+use std::fmt;
+use std::path::Path;
+use std::process::Command;
+
+const SAMPLE_WIDTH: usize = 320;
+const SAMPLE_HEIGHT: usize = 180;
+const SAMPLE_FRAMES: usize = 12;
+const CHANGED_PIXEL_DELTA: u32 = 24;
+const EDGE_DELTA: u32 = 64;
+
+const TERMINAL_MAX_COLORS: usize = 256;
+const TERMINAL_MIN_EDGES: f64 = 0.08;
+const SLIDES_MAX_MOTION: f64 = 0.02;
+const ANIMATION_MIN_MOTION: f64 = 0.15;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum, serde::Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ContentClass {
+    General,
+    Terminal,
+    Slides,
+    Animation,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct ContentStats {
+    pub colors: usize,
+    pub motion_fraction: f64,
+    pub edge_density: f64,
+}
+
+pub struct Preset {
+    pub max_fps: Option<u32>,
+    pub order: [Axis; 4],
+}
+
+impl ContentClass {
+    pub fn preset(self) -> Preset {
+        match self {
+            ContentClass::General => Preset {
+                max_fps: None,
+                order: DEFAULT_ORDER,
+            },
+            ContentClass::Terminal => Preset {
+                max_fps: Some(10),
+                order: [Axis::Fps, Axis::Colors, Axis::Lossy, Axis::Width],
+            },
+            ContentClass::Slides => Preset {
+                max_fps: Some(5),
+                order: [Axis::Fps, Axis::Colors, Axis::Width, Axis::Lossy],
+            },
+            ContentClass::Animation => Preset {
+                max_fps: None,
+                order: [Axis::Colors, Axis::Lossy, Axis::Width, Axis::Fps],
+            },
+        }
+    }
+}
+
+impl fmt::Display for ContentClass {
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            ContentClass::General => "general",
+            ContentClass::Terminal => "terminal",
+            ContentClass::Slides => "slides",
+            ContentClass::Animation => "animation",
+        };
+        formatter.write_str(name)
+    }
+}
+
+pub fn classify(stats: &ContentStats) -> ContentClass {
+    if stats.colors <= TERMINAL_MAX_COLORS && stats.edge_density >= TERMINAL_MIN_EDGES {
+        ContentClass::Terminal
+    } else if stats.motion_fraction < SLIDES_MAX_MOTION {
+        ContentClass::Slides
+    } else if stats.motion_fraction >= ANIMATION_MIN_MOTION {
+        ContentClass::Animation
+    } else {
+        ContentClass::General
+    }
+}
+
+fn channel_delta(first: &[u8], second: &[u8]) -> u32 {
+    first
+        .iter()
+        .zip(second)
+        .map(|(&a, &b)| a.abs_diff(b) as u32)
+        .max()
+        .unwrap_or(0)
+}
+
+fn frame_stats(frames: &[&[u8]]) -> ContentStats {
+    let stride = SAMPLE_WIDTH * 3;
+    let pixel_count = (SAMPLE_WIDTH * SAMPLE_HEIGHT) as f64;
+
+    let colors: usize = frames
+        .iter()
+        .map(|frame| {
+            frame
+                .chunks_exact(3)
+                .map(|pixel| (pixel[0] >> 3, pixel[1] >> 3, pixel[2] >> 3))
+                .collect::<HashSet<_>>()
+                .len()
+        })
+        .sum::<usize>()
+        / frames.len().max(1);
+
+    let changed: f64 = frames
+        .windows(2)
+        .map(|pair| {
+            pair[0]
+                .chunks_exact(3)
+                .zip(pair[1].chunks_exact(3))
+                .filter(|(before, after)| channel_delta(before, after) >= CHANGED_PIXEL_DELTA)
+                .count() as f64
+                / pixel_count
+        })
+        .sum();
+    let motion_fraction = changed / frames.len().saturating_sub(1).max(1) as f64;
+
+    let edges: f64 = frames
+        .iter()
+        .map(|frame| {
+            let edge_count = (0..frame.len() / 3)
+                .filter(|&pixel| {
+                    let offset = pixel * 3;
+                    let right = (pixel % SAMPLE_WIDTH + 1 < SAMPLE_WIDTH)
+                        .then(|| &frame[offset + 3..offset + 6]);
+                    let below = (offset + stride + 3 <= frame.len())
+                        .then(|| &frame[offset + stride..offset + stride + 3]);
+                    [right, below].into_iter().flatten().any(|neighbour| {
+                        channel_delta(&frame[offset..offset + 3], neighbour) >= EDGE_DELTA
+                    })
+                })
+                .count();
+            edge_count as f64 / pixel_count
+        })
+        .sum();
+    let edge_density = edges / frames.len().max(1) as f64;
+
+    ContentStats {
+        colors,
+        motion_fraction,
+        edge_density,
+    }
+}
+
+pub fn sample_content(
+    input: &Path,
+    range: &Segment,
+    crop: Option<Crop>,
+    cancel: &CancelToken,
+) -> Result<ContentStats> {
+    let sample_fps = (SAMPLE_FRAMES as f64 / range.duration_secs).min(4.0);
+    let crop_filter = crop
+        .map(|crop| format!("{},", crop.filter()))
+        .unwrap_or_default();
+    let filter =
+        format!("{crop_filter}fps={sample_fps:.4},scale={SAMPLE_WIDTH}:{SAMPLE_HEIGHT}:flags=area");
+
+    let mut command = Command::new("ffmpeg");
+    command.args(["-hide_banner", "-ss", &format!("{:.3}", range.start_secs)]);
+    command.args(["-t", &format!("{:.3}", range.duration_secs)]);
+    command.args(["-i"]);
+    command.arg(input);
+    command.args(["-vf", &filter, "-frames:v", &SAMPLE_FRAMES.to_string()]);
+    command.args(["-an", "-f", "rawvideo", "-pix_fmt", "rgb24", "-"]);
+
+    let output = cancel::run_ffmpeg(&mut command, cancel)?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(Error::EncodeFailed(stderr.into_owned()));
+    }
+
+    let frames: Vec<&[u8]> = output
+        .stdout
+        .chunks_exact(SAMPLE_WIDTH * SAMPLE_HEIGHT * 3)
+        .collect();
+    if frames.is_empty() {
+        return Err(Error::EncodeFailed(
+            "content analysis decoded no frames".into(),
+        ));
+    }
+
+    Ok(frame_stats(&frames))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(pixel: impl Fn(usize, usize) -> [u8; 3]) -> Vec<u8> {
+        (0..SAMPLE_HEIGHT)
+            .flat_map(|y| (0..SAMPLE_WIDTH).map(move |x| (x, y)))
+            .flat_map(|(x, y)| pixel(x, y))
+            .collect()
+    }
+
+    #[test]
+    fn text_like_frames_have_few_colors_and_dense_edges() {
+        let text = frame(|x, y| {
+            if (x / 2 + y / 3) % 2 == 0 {
+                [230, 230, 230]
+            } else {
+                [20, 20, 30]
+            }
+        });
+        let stats = frame_stats(&[&text, &text]);
+        assert_eq!(stats.colors, 2);
+        assert_eq!(stats.motion_fraction, 0.0);
+        assert!(stats.edge_density > 0.5);
+        assert_eq!(classify(&stats), ContentClass::Terminal);
+    }
+
+    #[test]
+    fn smooth_changing_gradients_read_as_animation() {
+        let first = frame(|x, y| [(x % 256) as u8, (y % 256) as u8, 128]);
+        let second = frame(|x, y| [((x + 60) % 256) as u8, (y % 256) as u8, 128]);
+        let stats = frame_stats(&[&first, &second]);
+        assert!(stats.colors > TERMINAL_MAX_COLORS);
+        assert!(stats.motion_fraction > ANIMATION_MIN_MOTION);
+        assert_eq!(classify(&stats), ContentClass::Animation);
+    }
+
+    #[test]
+    fn still_rich_frames_read_as_slides() {
+        let slide = frame(|x, y| [(x % 256) as u8, (y % 256) as u8, 64]);
+        let stats = frame_stats(&[&slide, &slide, &slide]);
+        assert_eq!(stats.motion_fraction, 0.0);
+        assert_eq!(classify(&stats), ContentClass::Slides);
+    }
+
+    #[test]
+    fn presets_keep_every_axis_once() {
+        for class in [
+            ContentClass::General,
+            ContentClass::Terminal,
+            ContentClass::Slides,
+            ContentClass::Animation,
+        ] {
+            let order = class.preset().order;
+            for axis in DEFAULT_ORDER {
+                assert_eq!(order.iter().filter(|&&other| other == axis).count(), 1);
+            }
+        }
+        assert_eq!(ContentClass::Terminal.preset().order[0], Axis::Fps);
+        assert_eq!(ContentClass::Animation.preset().order[3], Axis::Fps);
+    }
+}
@@ -0,0 +1,123 @@
+use crate::Segment;
+use crate::cancel::{self, CancelToken};
+use crate::encode::Crop;
+use crate::error::{Error, Result};
+use crate::probe::VideoInfo;
+use std::path::Path;
+use std::process::Command;
+
+const ANALYSIS_FPS: u32 = 4;
+const MOTION_THRESHOLD: u32 = 24;
+
+fn parse_cropdetect(output: &str) -> Option<Crop> {
+    let last = output
+        .lines()
+        .filter_map(|line| line.split_once("crop=").map(|(_, crop)| crop.trim()))
+        .next_back()?;
+
+    let fields: Vec<u32> = last
+        .split(':')
+        .map(|field| field.parse().ok())
+        .collect::<Option<_>>()?;
+
+    match fields[..] {
+        [width, height, x, y] if width > 0 && height > 0 => Some(Crop {
+            width,
+            height,
+            x,
+            y,
+        }),
+        _ => None,
+    }
+}
+
+fn useful_crop(crop: Crop, info: &VideoInfo) -> Option<Crop> {
+    let full_frame = crop.width >= info.width && crop.height >= info.height;
+    (!full_frame && crop.fits_within(info.width, info.height)).then_some(crop)
+}
+
+pub fn detect_crop(
+    input: &Path,
+    info: &VideoInfo,
+    range: &Segment,
+    cancel: &CancelToken,
+) -> Result<Option<Crop>> {
+    let filter = format!(
+        "fps={ANALYSIS_FPS},tblend=all_mode=difference,\
+         cropdetect=limit={MOTION_THRESHOLD}:round=2:reset=0"
+    );
+
+    let mut command = Command::new("ffmpeg");
+    command.args(["-hide_banner", "-ss", &format!("{:.3}", range.start_secs)]);
+    command.args(["-t", &format!("{:.3}", range.duration_secs)]);
+    command.args(["-i"]);
+    command.arg(input);
+    command.args(["-vf", &filter, "-an", "-f", "null", "-"]);
+
+    let output = cancel::run_ffmpeg(&mut command, cancel)?;
+    let stderr = String::from_utf8_lossy(&output.stderr);
+
+    if !output.status.success() {
+        return Err(Error::EncodeFailed(stderr.into_owned()));
+    }
+
+    Ok(parse_cropdetect(&stderr).and_then(|crop| useful_crop(crop, info)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn info() -> VideoInfo {
+        VideoInfo {
+            width: 1920,
+            height: 1080,
+            duration_secs: 10.0,
+            framerate: 30.0,
+        }
+    }
+
+    #[test]
+    fn parse_uses_final_accumulated_rectangle() {
+        let output = "[Parsed_cropdetect_2 @ 0x1] x1:900 x2:1019 y1:500 y2:579 w:120 h:80 x:900 y:500 pts:1 t:0.25 limit:0.094 crop=120:80:900:500\n\
+                      [Parsed_cropdetect_2 @ 0x1] x1:640 x2:1919 y1:360 y2:1079 w:1280 h:720 x:640 y:360 pts:2 t:0.50 limit:0.094 crop=1280:720:640:360\n";
+        assert_eq!(
+            parse_cropdetect(output),
+            Some(Crop {
+                width: 1280,
+                height: 720,
+                x: 640,
+                y: 360,
+            })
+        );
+    }
+
+    #[test]
+    fn parse_ignores_degenerate_rectangles() {
+        assert_eq!(parse_cropdetect("crop=-1904:-1064:1912:1072\n"), None);
+        assert_eq!(parse_cropdetect("crop=0:720:0:0\n"), None);
+        assert_eq!(parse_cropdetect("frame=  40 fps=0.0\n"), None);
+    }
+
+    #[test]
+    fn full_frame_crop_is_dropped() {
+        let crop = Crop {
+            width: 1920,
+            height: 1080,
+            x: 0,
+            y: 0,
+        };
+        assert_eq!(useful_crop(crop, &info()), None);
+    }
+
+    #[test]
+    fn partial_crop_is_kept() {
+        let crop = Crop {
+            width: 1280,
+            height: 720,
+            x: 640,
+            y: 360,
+        };
+        assert_eq!(useful_crop(crop, &info()), Some(crop));
+    }
+}
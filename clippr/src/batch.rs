@@ -0,0 +1,454 @@
+use crate::encode::OutputFormat;
+use crate::error::{Error, Result};
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+const VIDEO_EXTENSIONS: &[&str] = &["mp4", "m4v", "mov", "mkv", "webm", "avi"];
+
+pub struct BatchEntry<T> {
+    pub input: PathBuf,
+    pub result: Result<T>,
+}
+
+pub struct BatchSummary<T> {
+    pub entries: Vec<BatchEntry<T>>,
+    pub skipped: Vec<PathBuf>,
+}
+
+impl<T> BatchSummary<T> {
+    pub fn failed(&self) -> usize {
+        self.entries
+            .iter()
+            .filter(|entry| entry.result.is_err())
+            .count()
+    }
+
+    pub fn succeeded(&self) -> impl Iterator<Item = &T> {
+        self.entries
+            .iter()
+            .filter_map(|entry| entry.result.as_ref().ok())
+    }
+
+    pub fn render(&self, describe: impl Fn(&T) -> String) -> String {
+        let total = self.entries.len() + self.skipped.len();
+        let mut lines = vec![format!(
+            "batch: {} of {total} file(s) succeeded",
+            total - self.failed() - self.skipped.len()
+        )];
+        for entry in &self.entries {
+            lines.push(match &entry.result {
+                Ok(value) => format!("  ok       {} ({})", entry.input.display(), describe(value)),
+                Err(error) => format!("  failed   {}: {error}", entry.input.display()),
+            });
+        }
+        for input in &self.skipped {
+            lines.push(format!("  skipped  {}", input.display()));
+        }
+        lines.join("\n")
+    }
+}
+
+fn is_pattern(name: &str) -> bool {
+    name.contains(['*', '?'])
+}
+
+fn matches_pattern(pattern: &[char], name: &[char]) -> bool {
+    match pattern.split_first() {
+        None => name.is_empty(),
+        Some(('*', rest)) => (0..=name.len()).any(|skip| matches_pattern(rest, &name[skip..])),
+        Some(('?', rest)) => !name.is_empty() && matches_pattern(rest, &name[1..]),
+        Some((&literal, rest)) => {
+            name.first() == Some(&literal) && matches_pattern(rest, &name[1..])
+        }
+    }
+}
+
+fn is_video(path: &Path) -> bool {
+    path.extension()
+        .and_then(|extension| extension.to_str())
+        .is_some_and(|extension| {
+            VIDEO_EXTENSIONS
+                .iter()
+                .any(|video| video.eq_ignore_ascii_case(extension))
+        })
+}
+
+fn files_in(dir: &Path, keep: impl Fn(&Path) -> bool) -> Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_file() && keep(&path) {
+            files.push(path);
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+fn expand(path: &Path) -> Result<Vec<PathBuf>> {
+    let name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+
+    if path.is_dir() {
+        return files_in(path, is_video);
+    }
+    if !is_pattern(&name) {
+        return Ok(vec![path.to_path_buf()]);
+    }
+
+    let pattern: Vec<char> = name.chars().collect();
+    let dir = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    files_in(dir, |file| {
+        let file_name: Vec<char> = file
+            .file_name()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .chars()
+            .collect();
+        matches_pattern(&pattern, &file_name)
+    })
+}
+
+pub fn expand_inputs(
+    paths: &[PathBuf],
+    output_dir: Option<&Path>,
+    format: OutputFormat,
+) -> Vec<BatchEntry<()>> {
+    let mut entries = Vec::new();
+    let mut found: Vec<(PathBuf, bool)> = Vec::new();
+    for path in paths {
+        match expand(path) {
+            Ok(files) if files.is_empty() => entries.push(BatchEntry {
+                input: path.clone(),
+                result: Err(Error::InvalidInput("no videos found".into())),
+            }),
+            Ok(files) => {
+                let explicit = files.len() == 1 && files[0] == *path;
+                found.extend(files.into_iter().map(|file| (file, explicit)));
+            }
+            Err(error) => entries.push(BatchEntry {
+                input: path.clone(),
+                result: Err(error),
+            }),
+        }
+    }
+
+    let mut seen = HashSet::new();
+    found.retain(|(file, _)| seen.insert(file.clone()));
+    let stems: Vec<PathBuf> = found
+        .iter()
+        .filter_map(|(file, _)| planned_stem(file, output_dir, format).ok())
+        .collect();
+    found.retain(|(file, explicit)| {
+        *explicit
+            || !stems
+                .iter()
+                .any(|stem| crate::overwrites(file, stem, format))
+    });
+
+    let inputs = found.into_iter().map(|(file, _)| BatchEntry {
+        input: file,
+        result: Ok(()),
+    });
+    inputs.chain(entries).collect()
+}
+
+pub fn output_stem(input: &Path, output_dir: Option<&Path>) -> Option<PathBuf> {
+    Some(output_dir?.join(input.file_stem()?))
+}
+
+fn planned_stem(input: &Path, output_dir: Option<&Path>, format: OutputFormat) -> Result<PathBuf> {
+    crate::output_stem_from_args(input, output_stem(input, output_dir).as_deref(), format)
+}
+
+fn single_output(stem: &Path, format: OutputFormat) -> PathBuf {
+    let name = stem.file_name().unwrap_or_default().to_string_lossy();
+    stem.with_file_name(format!("{name}.{}", format.extension()))
+}
+
+pub fn check_distinct_outputs(
+    inputs: &[PathBuf],
+    output_dir: Option<&Path>,
+    format: OutputFormat,
+) -> Result<()> {
+    let mut stems: Vec<(PathBuf, &PathBuf)> = Vec::new();
+    for input in inputs {
+        let stem = planned_stem(input, output_dir, format)?;
+        if let Some((_, first)) = stems.iter().find(|(other, _)| *other == stem) {
+            return Err(Error::InvalidInput(format!(
+                "{} and {} would both write {}",
+                first.display(),
+                input.display(),
+                stem.display()
+            )));
+        }
+        if let Some((other, first)) = stems.iter().find(|(other, _)| {
+            crate::overwrites(&single_output(&stem, format), other, format)
+                || crate::overwrites(&single_output(other, format), &stem, format)
+        }) {
+            return Err(Error::InvalidInput(format!(
+                "{} and {} would write overlapping chunks ({} and {})",
+                first.display(),
+                input.display(),
+                other.display(),
+                stem.display()
+            )));
+        }
+        if let Some(other) = inputs
+            .iter()
+            .find(|other| crate::overwrites(other, &stem, format))
+        {
+            return Err(Error::InvalidInput(format!(
+                "output for {} would overwrite the input {}",
+                input.display(),
+                other.display()
+            )));
+        }
+        stems.push((stem, input));
+    }
+    Ok(())
+}
+
+pub fn run_batch<T>(
+    inputs: Vec<BatchEntry<()>>,
+    fail_fast: bool,
+    mut convert: impl FnMut(usize, &Path) -> Result<T>,
+) -> BatchSummary<T> {
+    let mut summary = BatchSummary {
+        entries: Vec::new(),
+        skipped: Vec::new(),
+    };
+
+    let mut inputs = inputs.into_iter().enumerate();
+    for (index, entry) in inputs.by_ref() {
+        let result = entry.result.and_then(|()| convert(index, &entry.input));
+        let stop = match &result {
+            Err(Error::Cancelled) => true,
+            Err(_) => fail_fast,
+            Ok(_) => false,
+        };
+        summary.entries.push(BatchEntry {
+            input: entry.input,
+            result,
+        });
+        if stop {
+            break;
+        }
+    }
+    summary.skipped = inputs.map(|(_, entry)| entry.input).collect();
+
+    summary
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::test_dir;
+
+    fn expanded(paths: &[PathBuf], format: OutputFormat) -> Vec<PathBuf> {
+        expand_inputs(paths, None, format)
+            .into_iter()
+            .filter(|entry| entry.result.is_ok())
+            .map(|entry| entry.input)
+            .collect()
+    }
+
+    fn inputs(names: &[&str]) -> Vec<BatchEntry<()>> {
+        names
+            .iter()
+            .map(|name| BatchEntry {
+                input: PathBuf::from(name),
+                result: Ok(()),
+            })
+            .collect()
+    }
+
+    fn matches(pattern: &str, name: &str) -> bool {
+        let pattern: Vec<char> = pattern.chars().collect();
+        let name: Vec<char> = name.chars().collect();
+        matches_pattern(&pattern, &name)
+    }
+
+    #[test]
+    fn patterns_match_stars_and_single_characters() {
+        assert!(matches("*.mp4", "demo.mp4"));
+        assert!(matches("take-?.mov", "take-2.mov"));
+        assert!(matches("*", ""));
+        assert!(!matches("*.mp4", "demo.mp4.gif"));
+        assert!(!matches("take-?.mov", "take-10.mov"));
+    }
+
+    #[test]
+    fn directories_expand_to_sorted_videos() {
+        let dir = test_dir("batch-dir");
+        for name in ["b.mp4", "a.MOV", "a.gif", "notes.txt"] {
+            std::fs::write(dir.join(name), "").unwrap();
+        }
+
+        let inputs = expanded(std::slice::from_ref(&dir), OutputFormat::Gif);
+
+        assert_eq!(inputs, vec![dir.join("a.MOV"), dir.join("b.mp4")]);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn globs_expand_and_duplicates_are_dropped() {
+        let dir = test_dir("batch-glob");
+        for name in ["take-1.mp4", "take-2.mp4", "intro.mp4"] {
+            std::fs::write(dir.join(name), "").unwrap();
+        }
+
+        let inputs = expanded(
+            &[dir.join("take-2.mp4"), dir.join("take-*.mp4")],
+            OutputFormat::Gif,
+        );
+
+        assert_eq!(inputs, vec![dir.join("take-2.mp4"), dir.join("take-1.mp4")]);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn empty_matches_are_failed_entries_but_missing_files_pass_through() {
+        let dir = test_dir("batch-empty");
+        std::fs::write(dir.join("demo.mp4"), "").unwrap();
+
+        let entries = expand_inputs(
+            &[
+                dir.join("*.mkv"),
+                dir.join("demo.mp4"),
+                dir.join("missing.mp4"),
+            ],
+            None,
+            OutputFormat::Gif,
+        );
+
+        let inputs: Vec<_> = entries.iter().map(|entry| entry.input.clone()).collect();
+        assert_eq!(
+            inputs,
+            vec![
+                dir.join("demo.mp4"),
+                dir.join("missing.mp4"),
+                dir.join("*.mkv")
+            ]
+        );
+        assert!(entries[2].result.is_err());
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        let summary = run_batch(entries, false, |_, _| Ok(()));
+        assert_eq!(summary.failed(), 1);
+        assert_eq!(summary.succeeded().count(), 2);
+    }
+
+    #[test]
+    fn earlier_mp4_outputs_are_not_picked_up_again() {
+        let dir = test_dir("batch-rerun");
+        for name in [
+            "demo.mp4",
+            "demo_clippr_001.mp4",
+            "demo_clippr_002.mp4",
+            "demo_clippr.mp4",
+        ] {
+            std::fs::write(dir.join(name), "").unwrap();
+        }
+
+        let mp4 = expanded(std::slice::from_ref(&dir), OutputFormat::Mp4);
+        let gif = expanded(std::slice::from_ref(&dir), OutputFormat::Gif);
+        let explicit = expanded(&[dir.join("demo_clippr_001.mp4")], OutputFormat::Mp4);
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(mp4, vec![dir.join("demo.mp4")]);
+        assert_eq!(gif.len(), 4);
+        assert_eq!(explicit, vec![dir.join("demo_clippr_001.mp4")]);
+    }
+
+    #[test]
+    fn output_dir_gets_one_stem_per_input() {
+        let inputs = [PathBuf::from("a/demo.mp4"), PathBuf::from("b/intro.mov")];
+        let out = Path::new("out");
+
+        assert_eq!(
+            output_stem(&inputs[0], Some(out)),
+            Some(PathBuf::from("out/demo"))
+        );
+        assert_eq!(output_stem(&inputs[0], None), None);
+        assert!(check_distinct_outputs(&inputs, Some(out), OutputFormat::Gif).is_ok());
+    }
+
+    #[test]
+    fn colliding_stems_are_rejected() {
+        let inputs = [PathBuf::from("a/demo.mp4"), PathBuf::from("b/demo.mov")];
+        assert!(check_distinct_outputs(&inputs, None, OutputFormat::Gif).is_ok());
+        assert!(
+            check_distinct_outputs(&inputs, Some(Path::new("out")), OutputFormat::Gif).is_err()
+        );
+    }
+
+    #[test]
+    fn stems_that_share_chunk_names_are_rejected() {
+        let inputs = [PathBuf::from("clip.mp4"), PathBuf::from("clip_001.mp4")];
+        assert!(check_distinct_outputs(&inputs, None, OutputFormat::Gif).is_err());
+
+        let reversed = [PathBuf::from("clip_001.mp4"), PathBuf::from("clip.mp4")];
+        assert!(check_distinct_outputs(&reversed, None, OutputFormat::Gif).is_err());
+
+        let apart = [PathBuf::from("a/clip.mp4"), PathBuf::from("b/clip_001.mp4")];
+        assert!(check_distinct_outputs(&apart, None, OutputFormat::Gif).is_ok());
+
+        let unrelated = [PathBuf::from("clip.mp4"), PathBuf::from("clip_1.mp4")];
+        assert!(check_distinct_outputs(&unrelated, None, OutputFormat::Gif).is_ok());
+    }
+
+    #[test]
+    fn outputs_landing_on_inputs_are_rejected() {
+        let inputs = [PathBuf::from("clips/demo.mp4")];
+        let clips = Some(Path::new("clips"));
+
+        assert!(check_distinct_outputs(&inputs, None, OutputFormat::Mp4).is_ok());
+        assert!(check_distinct_outputs(&inputs, clips, OutputFormat::Mp4).is_err());
+        assert!(check_distinct_outputs(&inputs, clips, OutputFormat::Gif).is_ok());
+
+        let others = [
+            PathBuf::from("clips/demo.mov"),
+            PathBuf::from("clips/demo_001.mp4"),
+        ];
+        assert!(check_distinct_outputs(&others, None, OutputFormat::Mp4).is_err());
+    }
+
+    #[test]
+    fn failures_are_recorded_and_the_batch_continues() {
+        let convert = |index: usize, _: &Path| match index {
+            1 => Err(Error::InvalidInput("broken".into())),
+            _ => Ok(index),
+        };
+
+        let summary = run_batch(inputs(&["a.mp4", "b.mp4", "c.mp4"]), false, convert);
+        assert_eq!(summary.entries.len(), 3);
+        assert_eq!(summary.failed(), 1);
+        assert_eq!(summary.succeeded().copied().collect::<Vec<_>>(), vec![0, 2]);
+
+        let summary = run_batch(inputs(&["a.mp4", "b.mp4", "c.mp4"]), true, convert);
+        assert_eq!(summary.entries.len(), 2);
+        assert_eq!(summary.skipped, vec![PathBuf::from("c.mp4")]);
+        assert_eq!(
+            summary.render(|index| format!("#{index}")),
+            "batch: 1 of 3 file(s) succeeded\n  \
+             ok       a.mp4 (#0)\n  \
+             failed   b.mp4: invalid input: broken\n  \
+             skipped  c.mp4"
+        );
+    }
+
+    #[test]
+    fn cancelling_stops_the_batch() {
+        let summary = run_batch(inputs(&["a.mp4", "b.mp4"]), false, |_, _| {
+            Err::<(), _>(Error::Cancelled)
+        });
+        assert_eq!(summary.entries.len(), 1);
+        assert_eq!(summary.skipped.len(), 1);
+    }
+}
@@ -1,10 +1,90 @@
-use clap::Parser;
-use std::path::PathBuf;
+use clap::{Args, Parser, Subcommand};
+use clippr::ConvertOptions;
+use clippr::analyze::ContentClass;
+use clippr::batch;
+use clippr::encode::{Backend, Crop, OutputFormat};
+use clippr::scene::Boundaries;
+use clippr::strategy::FitMode;
+use clippr::timestamp::parse_timestamp;
+use std::path::{Path, PathBuf};
+
+fn parse_speed(raw: &str) -> Result<f64, String> {
+    let factor = raw.strip_suffix(['x', 'X']).unwrap_or(raw);
+    match factor.parse::<f64>() {
+        Ok(speed) if speed > 0.0 && speed.is_finite() => Ok(speed),
+        _ => Err(format!("'{raw}' is not a positive speed factor")),
+    }
+}
 
 #[derive(Parser)]
-#[command(name = "clippr", about = "Convert MP4 to chunked GitHub-friendly GIFs")]
+#[command(
+    name = "clippr",
+    about = "Convert MP4 to chunked GitHub-friendly GIFs",
+    args_conflicts_with_subcommands = true,
+    subcommand_negates_reqs = true,
+    arg_required_else_help = true
+)]
 pub struct Cli {
-    pub input: Option<PathBuf>,
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    #[command(flatten)]
+    convert: ConvertArgs,
+
+    #[arg(long, hide = true, exclusive = true)]
+    gui: bool,
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    #[command(about = "Convert a video into size-limited chunks (the default)")]
+    Convert(ConvertArgs),
+
+    #[command(about = "Encode a few sample windows and print the planned chunks")]
+    Estimate(EncodeArgs),
+
+    #[command(about = "Print the video's dimensions, duration and frame rate")]
+    Probe(ProbeArgs),
+
+    #[command(about = "Launch the graphical interface")]
+    Gui,
+}
+
+#[derive(Args)]
+pub struct ProbeArgs {
+    pub input: PathBuf,
+
+    #[arg(long, help = "Print the video info as JSON")]
+    pub json: bool,
+}
+
+#[derive(Args)]
+pub struct ConvertArgs {
+    #[command(flatten)]
+    pub encode: EncodeArgs,
+
+    #[arg(long, help = "Stop the batch at the first input that fails")]
+    pub fail_fast: bool,
+
+    #[arg(
+        long,
+        conflicts_with = "manifest",
+        help = "Encode a few sample windows and print the planned chunks without writing any"
+    )]
+    pub dry_run: bool,
+
+    #[arg(long, help = "Write <output>.manifest.json next to the chunks")]
+    pub manifest: bool,
+}
+
+#[derive(Args)]
+pub struct EncodeArgs {
+    #[arg(
+        required = true,
+        value_name = "INPUT",
+        help = "Video files, directories of videos, or patterns like clips/*.mp4"
+    )]
+    pub inputs: Vec<PathBuf>,
 
     #[arg(
         short,
@@ -13,6 +93,13 @@ pub struct Cli {
     )]
     pub output: Option<PathBuf>,
 
+    #[arg(
+        long,
+        conflicts_with = "output",
+        help = "Write each input's chunks to this directory, named after the input"
+    )]
+    pub output_dir: Option<PathBuf>,
+
     #[arg(long, default_value = "10", help = "Max file size per chunk in MB")]
     pub max_size_mb: f64,
 
@@ -22,8 +109,11 @@ pub struct Cli {
     #[arg(long, default_value = "15", help = "Starting frames per second")]
     pub fps: u32,
 
-    #[arg(long, default_value = "256", help = "Starting palette color count")]
-    pub colors: u32,
+    #[arg(
+        long,
+        help = "Starting palette color count [default: 256; apng is lossless and mp4 ignores it]"
+    )]
+    pub colors: Option<u32>,
 
     #[arg(
         long,
@@ -32,6 +122,234 @@ pub struct Cli {
     )]
     pub chunk_secs: f64,
 
-    #[arg(long, help = "Launch the graphical interface")]
-    pub gui: bool,
+    #[arg(
+        long,
+        value_parser = parse_timestamp,
+        help = "Skip everything before this time (seconds, mm:ss or hh:mm:ss)"
+    )]
+    pub start: Option<f64>,
+
+    #[arg(
+        long,
+        value_parser = parse_timestamp,
+        help = "Stop converting at this time (seconds, mm:ss or hh:mm:ss)"
+    )]
+    pub end: Option<f64>,
+
+    #[arg(long, help = "Crop to a WxH+X+Y region of the source before scaling")]
+    pub crop: Option<Crop>,
+
+    #[arg(
+        long,
+        conflicts_with = "crop",
+        help = "Detect the region where motion occurs and crop every chunk to it"
+    )]
+    pub auto_crop: bool,
+
+    #[arg(
+        long,
+        default_value = "1",
+        value_parser = parse_speed,
+        help = "Playback speed factor, e.g. 2 or 4x for a time-lapse"
+    )]
+    pub speed: f64,
+
+    #[arg(
+        long,
+        value_enum,
+        default_value = "gif",
+        help = "Output format for each chunk"
+    )]
+    pub format: OutputFormat,
+
+    #[arg(long, help = "Keep the audio track (mp4 only)")]
+    pub keep_audio: bool,
+
+    #[arg(
+        long,
+        value_enum,
+        default_value = "ffmpeg",
+        help = "GIF encoder: ffmpeg, or native to decode once and quantize in-process"
+    )]
+    pub backend: Backend,
+
+    #[arg(
+        short,
+        long,
+        help = "Number of segments to encode in parallel [default: CPU count]"
+    )]
+    pub jobs: Option<usize>,
+
+    #[arg(
+        long,
+        value_enum,
+        default_value = "ladder",
+        help = "Size fitting for chunks that cannot be split: ladder, bisect or quality"
+    )]
+    pub fit: FitMode,
+
+    #[arg(
+        long,
+        default_value = "0.1",
+        help = "Bisect fitting stops once a chunk is within this fraction of the max size"
+    )]
+    pub tolerance: f64,
+
+    #[arg(
+        long,
+        value_enum,
+        default_value = "fixed",
+        help = "Chunk boundaries: fixed chunk-secs slices, or scene to snap cuts to low-motion frames"
+    )]
+    pub boundaries: Boundaries,
+
+    #[arg(
+        long = "snap-window",
+        default_value = "1.0",
+        help = "How far (in seconds) a scene boundary may move from its fixed position"
+    )]
+    pub snap_window_secs: f64,
+
+    #[arg(
+        long,
+        value_enum,
+        help = "Content preset for starting fps and reduction order [default: detected from samples; terminal caps fps at 10 and slides at 5, use general to keep --fps]"
+    )]
+    pub content: Option<ContentClass>,
+
+    #[arg(
+        long = "max-pause",
+        help = "Shorten stretches with no visual change to at most this many seconds"
+    )]
+    pub max_pause_secs: Option<f64>,
+
+    #[arg(long, help = "Print a JSON report to stdout")]
+    pub json: bool,
+}
+
+impl Cli {
+    pub fn into_command(self) -> Command {
+        if self.gui {
+            return Command::Gui;
+        }
+        self.command.unwrap_or(Command::Convert(self.convert))
+    }
+}
+
+impl EncodeArgs {
+    pub fn options(&self, input: &Path) -> ConvertOptions {
+        ConvertOptions {
+            input: input.to_path_buf(),
+            output: batch::output_stem(input, self.output_dir.as_deref())
+                .or_else(|| self.output.clone()),
+            max_size_mb: self.max_size_mb,
+            width: self.width,
+            fps: self.fps,
+            colors: self.colors.unwrap_or(self.format.default_colors()),
+            chunk_secs: self.chunk_secs,
+            start_secs: self.start.unwrap_or(0.0),
+            end_secs: self.end,
+            crop: self.crop,
+            auto_crop: self.auto_crop,
+            speed: self.speed,
+            format: self.format,
+            keep_audio: self.keep_audio,
+            backend: self.backend,
+            jobs: self.jobs.unwrap_or_else(clippr::available_jobs),
+            fit: self.fit,
+            tolerance: self.tolerance,
+            boundaries: self.boundaries,
+            snap_window_secs: self.snap_window_secs,
+            content: self.content,
+            max_pause_secs: self.max_pause_secs,
+            manifest: false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn command(args: &[&str]) -> Command {
+        Cli::try_parse_from(args).unwrap().into_command()
+    }
+
+    #[test]
+    fn bare_input_converts() {
+        match command(&["clippr", "demo.mp4", "--width", "640"]) {
+            Command::Convert(args) => {
+                assert_eq!(args.encode.inputs, vec![PathBuf::from("demo.mp4")]);
+                assert_eq!(args.encode.width, 640);
+            }
+            _ => panic!("expected convert"),
+        }
+    }
+
+    #[test]
+    fn subcommands_take_their_own_arguments() {
+        assert!(matches!(
+            command(&["clippr", "convert", "demo.mp4", "--fps", "10"]),
+            Command::Convert(args) if args.encode.fps == 10
+        ));
+        assert!(matches!(
+            command(&["clippr", "estimate", "demo.mp4"]),
+            Command::Estimate(args) if args.inputs == [PathBuf::from("demo.mp4")]
+        ));
+        assert!(matches!(
+            command(&["clippr", "probe", "demo.mp4", "--json"]),
+            Command::Probe(args) if args.json
+        ));
+        assert!(matches!(command(&["clippr", "gui"]), Command::Gui));
+    }
+
+    #[test]
+    fn estimate_rejects_convert_only_flags() {
+        for flag in ["--manifest", "--dry-run", "--fail-fast"] {
+            assert!(Cli::try_parse_from(["clippr", "estimate", "demo.mp4", flag]).is_err());
+            assert!(Cli::try_parse_from(["clippr", "convert", "demo.mp4", flag]).is_ok());
+        }
+        assert!(Cli::try_parse_from(["clippr", "demo.mp4", "--dry-run", "--manifest"]).is_err());
+    }
+
+    #[test]
+    fn gui_flag_is_kept_as_an_alias() {
+        assert!(matches!(command(&["clippr", "--gui"]), Command::Gui));
+        assert!(Cli::try_parse_from(["clippr", "--gui", "demo.mp4"]).is_err());
+    }
+
+    #[test]
+    fn several_inputs_share_one_output_dir() {
+        let Command::Convert(args) = command(&[
+            "clippr",
+            "a.mp4",
+            "clips/b.mov",
+            "--output-dir",
+            "out",
+            "--fail-fast",
+        ]) else {
+            panic!("expected convert");
+        };
+        assert!(args.fail_fast);
+        let outputs: Vec<Option<PathBuf>> = args
+            .encode
+            .inputs
+            .iter()
+            .map(|input| args.encode.options(input).output)
+            .collect();
+        assert_eq!(
+            outputs,
+            vec![Some(PathBuf::from("out/a")), Some(PathBuf::from("out/b"))]
+        );
+        assert!(
+            Cli::try_parse_from(["clippr", "a.mp4", "-o", "x", "--output-dir", "out"]).is_err()
+        );
+    }
+
+    #[test]
+    fn missing_input_is_an_error_not_the_gui() {
+        assert!(Cli::try_parse_from(["clippr"]).is_err());
+        assert!(Cli::try_parse_from(["clippr", "--width", "640"]).is_err());
+        assert!(Cli::try_parse_from(["clippr", "convert"]).is_err());
+    }
 }
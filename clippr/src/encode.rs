@@ -1,41 +1,717 @@
+use crate::Segment;
+use crate::analyze::{self, ContentStats};
+use crate::autocrop;
+use crate::cancel::{self, CancelToken};
 use crate::error::{Error, Result};
+use crate::optimize;
+use crate::probe::{self, VideoInfo};
+use crate::quality;
+use crate::scene::{self, MotionSample};
+use std::fmt;
 use std::path::Path;
 use std::process::Command;
+use std::str::FromStr;
 
+pub trait Encoder: Sync {
+    fn probe(&self, input: &Path) -> Result<VideoInfo> {
+        probe::probe(input)
+    }
+
+    fn detect_motion(
+        &self,
+        input: &Path,
+        range: &Segment,
+        cancel: &CancelToken,
+    ) -> Result<Vec<MotionSample>> {
+        scene::detect_motion(input, range, cancel)
+    }
+
+    fn detect_crop(
+        &self,
+        input: &Path,
+        info: &VideoInfo,
+        range: &Segment,
+        cancel: &CancelToken,
+    ) -> Result<Option<Crop>> {
+        autocrop::detect_crop(input, info, range, cancel)
+    }
+
+    fn analyze_content(
+        &self,
+        input: &Path,
+        range: &Segment,
+        crop: Option<Crop>,
+        cancel: &CancelToken,
+    ) -> Result<ContentStats> {
+        analyze::sample_content(input, range, crop, cancel)
+    }
+
+    fn encode(
+        &self,
+        input: &Path,
+        output: &Path,
+        params: &EncodeParams,
+        cancel: &CancelToken,
+    ) -> Result<u64>;
+
+    fn optimize(&self, output: &Path, params: &EncodeParams, cancel: &CancelToken) -> Result<u64> {
+        match params.format {
+            OutputFormat::Gif => optimize::optimize_gif(output, params.lossy, cancel),
+            _ => Ok(0),
+        }
+    }
+
+    fn measure_quality(
+        &self,
+        input: &Path,
+        output: &Path,
+        params: &EncodeParams,
+        reference: &EncodeParams,
+        cancel: &CancelToken,
+    ) -> Result<f64> {
+        quality::measure_ssim(input, output, params, reference, cancel)
+    }
+}
+
+pub struct FfmpegEncoder;
+
+impl Encoder for FfmpegEncoder {
+    fn encode(
+        &self,
+        input: &Path,
+        output: &Path,
+        params: &EncodeParams,
+        cancel: &CancelToken,
+    ) -> Result<u64> {
+        encode(input, output, params, cancel)
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum, serde::Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum OutputFormat {
+    #[default]
+    Gif,
+    Webp,
+    WebpLossless,
+    Mp4,
+    Apng,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum Backend {
+    #[default]
+    Ffmpeg,
+    Native,
+}
+
+pub const TRUE_COLOR: u32 = 1 << 24;
+
+impl OutputFormat {
+    pub fn extension(self) -> &'static str {
+        match self {
+            OutputFormat::Gif => "gif",
+            OutputFormat::Webp | OutputFormat::WebpLossless => "webp",
+            OutputFormat::Mp4 => "mp4",
+            OutputFormat::Apng => "png",
+        }
+    }
+
+    pub fn default_colors(self) -> u32 {
+        match self {
+            OutputFormat::Apng | OutputFormat::Mp4 => TRUE_COLOR,
+            _ => 256,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+pub struct Crop {
+    pub width: u32,
+    pub height: u32,
+    pub x: u32,
+    pub y: u32,
+}
+
+impl Crop {
+    pub fn fits_within(&self, width: u32, height: u32) -> bool {
+        self.x.saturating_add(self.width) <= width && self.y.saturating_add(self.height) <= height
+    }
+
+    pub(crate) fn filter(&self) -> String {
+        format!("crop={}:{}:{}:{}", self.width, self.height, self.x, self.y)
+    }
+}
+
+impl fmt::Display for Crop {
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            formatter,
+            "{}x{}+{}+{}",
+            self.width, self.height, self.x, self.y
+        )
+    }
+}
+
+impl FromStr for Crop {
+    type Err = Error;
+
+    fn from_str(raw: &str) -> Result<Self> {
+        let invalid = || Error::InvalidInput(format!("'{raw}' is not a crop (expected WxH+X+Y)"));
+
+        let (size, offset) = match raw.split_once('+') {
+            Some((size, offset)) => (size, Some(offset)),
+            None => (raw, None),
+        };
+        let (width, height) = size.split_once('x').ok_or_else(invalid)?;
+        let (x, y) = match offset {
+            Some(offset) => offset.split_once('+').ok_or_else(invalid)?,
+            None => ("0", "0"),
+        };
+
+        let parse = |field: &str| field.parse::<u32>().map_err(|_| invalid());
+        let crop = Crop {
+            width: parse(width)?,
+            height: parse(height)?,
+            x: parse(x)?,
+            y: parse(y)?,
+        };
+
+        if crop.width == 0 || crop.height == 0 {
+            return Err(invalid());
+        }
+
+        Ok(crop)
+    }
+}
+
+const AUDIO_BITRATE_KBPS: u32 = 128;
+const MIN_VIDEO_BITRATE_KBPS: u32 = 50;
+const CONTAINER_OVERHEAD: f64 = 0.97;
+
+#[derive(Debug, Clone)]
 pub struct EncodeParams {
+    pub format: OutputFormat,
     pub width: u32,
     pub fps: u32,
     pub colors: u32,
+    pub crop: Option<Crop>,
+    pub speed: f64,
     pub start_secs: f64,
     pub duration_secs: f64,
+    pub skip: Vec<Segment>,
+    pub lossy: u32,
+    pub target_bytes: u64,
+    pub audio: bool,
+}
+
+impl EncodeParams {
+    pub fn output_secs(&self) -> f64 {
+        let skipped: f64 = self.skip.iter().map(|gap| gap.duration_secs).sum();
+        (self.duration_secs - skipped) / self.speed
+    }
+
+    fn skip_expression(&self) -> String {
+        let ranges: Vec<String> = self
+            .skip
+            .iter()
+            .map(|gap| {
+                let start = gap.start_secs - self.start_secs;
+                format!("between(t,{:.3},{:.3})", start, start + gap.duration_secs)
+            })
+            .collect();
+        format!("not({})", ranges.join("+"))
+    }
+}
+
+fn webp_quality(colors: u32) -> u32 {
+    40 + colors.min(256) * 40 / 256
+}
+
+pub(crate) fn source_filters(params: &EncodeParams) -> String {
+    let mut filters = String::new();
+    if let Some(crop) = params.crop {
+        filters.push_str(&format!("{},", crop.filter()));
+    }
+    if !params.skip.is_empty() {
+        filters.push_str(&format!(
+            "select='{}',setpts=N/FRAME_RATE/TB,",
+            params.skip_expression()
+        ));
+    }
+    if params.speed != 1.0 {
+        filters.push_str(&format!("setpts=PTS/{},", params.speed));
+    }
+    filters
 }
 
-pub fn encode(input: &Path, output: &Path, params: &EncodeParams) -> Result<u64> {
-    let filter = format!(
-        "fps={fps},scale={width}:-1:flags=lanczos,\
+fn audio_filters(params: &EncodeParams) -> Option<String> {
+    let mut filters = Vec::new();
+    if !params.skip.is_empty() {
+        filters.push(format!(
+            "aselect='{}',asetpts=N/SR/TB",
+            params.skip_expression()
+        ));
+    }
+    if params.speed != 1.0 {
+        filters.push(atempo_filter(params.speed));
+    }
+    (!filters.is_empty()).then(|| filters.join(","))
+}
+
+fn atempo_filter(speed: f64) -> String {
+    let mut remaining = speed;
+    let mut stages = Vec::new();
+    while remaining > 2.0 {
+        stages.push("atempo=2".to_string());
+        remaining /= 2.0;
+    }
+    while remaining < 0.5 {
+        stages.push("atempo=0.5".to_string());
+        remaining /= 0.5;
+    }
+    stages.push(format!("atempo={remaining}"));
+    stages.join(",")
+}
+
+fn palette_filter(params: &EncodeParams, dither: &str) -> String {
+    format!(
+        "{source}fps={fps},mpdecimate,scale={width}:-1:flags=lanczos,\
          split[s0][s1];\
          [s0]palettegen=max_colors={colors}:stats_mode=diff[p];\
-         [s1][p]paletteuse=dither=floyd_steinberg",
+         [s1][p]paletteuse=dither={dither}:diff_mode=rectangle",
+        source = source_filters(params),
         fps = params.fps,
         width = params.width,
         colors = params.colors,
-    );
+    )
+}
 
+fn scale_filter(params: &EncodeParams) -> String {
+    format!(
+        "{source}fps={fps},mpdecimate,scale={width}:-1:flags=lanczos",
+        source = source_filters(params),
+        fps = params.fps,
+        width = params.width,
+    )
+}
+
+fn video_filter(params: &EncodeParams) -> String {
+    format!(
+        "{source}fps={fps},scale={width}:-2:flags=lanczos",
+        source = source_filters(params),
+        fps = params.fps,
+        width = params.width & !1,
+    )
+}
+
+fn video_bitrate_kbps(target_bytes: u64, duration_secs: f64, audio: bool) -> u32 {
+    let total_kbps = (target_bytes as f64 * 8.0 * CONTAINER_OVERHEAD) / (duration_secs * 1000.0);
+    let audio_kbps = if audio { AUDIO_BITRATE_KBPS } else { 0 };
+    ((total_kbps as u32).saturating_sub(audio_kbps)).max(MIN_VIDEO_BITRATE_KBPS)
+}
+
+fn ffmpeg_command(input: &Path, params: &EncodeParams) -> Command {
     let mut command = Command::new("ffmpeg");
     command.args(["-y", "-ss", &format!("{:.3}", params.start_secs)]);
     command.args(["-t", &format!("{:.3}", params.duration_secs)]);
     command.args(["-i"]);
     command.arg(input);
-    command.args(["-vf", &filter]);
-    command.arg(output);
+    command
+}
 
-    let result = command.output().map_err(|_| Error::FfmpegNotFound)?;
+fn run(mut command: Command, cancel: &CancelToken) -> Result<()> {
+    let result = cancel::run_ffmpeg(&mut command, cancel)?;
 
     if !result.status.success() {
         let stderr = String::from_utf8_lossy(&result.stderr);
         return Err(Error::EncodeFailed(stderr.into_owned()));
     }
 
+    Ok(())
+}
+
+fn encode_video(
+    input: &Path,
+    output: &Path,
+    params: &EncodeParams,
+    cancel: &CancelToken,
+) -> Result<u64> {
+    let bitrate = format!(
+        "{}k",
+        video_bitrate_kbps(params.target_bytes, params.output_secs(), params.audio)
+    );
+    let passlog = output.with_extension("passlog");
+
+    let mut first_pass = ffmpeg_command(input, params);
+    first_pass.args(["-vf", &video_filter(params)]);
+    first_pass.args(["-c:v", "libx264", "-preset", "slow", "-b:v", &bitrate]);
+    first_pass.args(["-pass", "1", "-passlogfile"]);
+    first_pass.arg(&passlog);
+    first_pass.args(["-an", "-f", "null", "-"]);
+
+    let mut second_pass = ffmpeg_command(input, params);
+    second_pass.args(["-vf", &video_filter(params)]);
+    second_pass.args(["-c:v", "libx264", "-preset", "slow", "-b:v", &bitrate]);
+    second_pass.args(["-pass", "2", "-passlogfile"]);
+    second_pass.arg(&passlog);
+    second_pass.args(["-pix_fmt", "yuv420p", "-movflags", "+faststart"]);
+    if params.audio {
+        if let Some(filters) = audio_filters(params) {
+            second_pass.args(["-af", &filters]);
+        }
+        second_pass.args(["-c:a", "aac", "-b:a", &format!("{AUDIO_BITRATE_KBPS}k")]);
+    } else {
+        second_pass.arg("-an");
+    }
+    second_pass.arg(output);
+
+    let result = run(first_pass, cancel).and_then(|_| run(second_pass, cancel));
+
+    let passlog_name = passlog.file_name().unwrap_or_default().to_string_lossy();
+    for suffix in ["-0.log", "-0.log.mbtree"] {
+        let _ = std::fs::remove_file(passlog.with_file_name(format!("{passlog_name}{suffix}")));
+    }
+
+    result?;
+
+    let metadata = std::fs::metadata(output)?;
+    Ok(metadata.len())
+}
+
+pub fn encode(
+    input: &Path,
+    output: &Path,
+    params: &EncodeParams,
+    cancel: &CancelToken,
+) -> Result<u64> {
+    let mut command = ffmpeg_command(input, params);
+
+    match params.format {
+        OutputFormat::Gif => {
+            command.args(["-vf", &palette_filter(params, "floyd_steinberg")]);
+        }
+        OutputFormat::Apng => {
+            if params.colors >= TRUE_COLOR {
+                command.args(["-vf", &scale_filter(params), "-pix_fmt", "rgb24"]);
+            } else {
+                command.args(["-vf", &palette_filter(params, "none")]);
+            }
+            command.args(["-f", "apng", "-plays", "0", "-pred", "mixed"]);
+        }
+        OutputFormat::Webp => {
+            command.args(["-vf", &scale_filter(params)]);
+            command.args(["-c:v", "libwebp_anim", "-loop", "0", "-lossless", "0"]);
+            command.args(["-quality", &webp_quality(params.colors).to_string()]);
+        }
+        OutputFormat::WebpLossless => {
+            command.args(["-vf", &scale_filter(params)]);
+            command.args(["-c:v", "libwebp_anim", "-loop", "0", "-lossless", "1"]);
+            command.args(["-compression_level", "6"]);
+        }
+        OutputFormat::Mp4 => return encode_video(input, output, params, cancel),
+    }
+
+    command.args(["-fps_mode", "vfr"]);
+    command.arg(output);
+    run(command, cancel)?;
+
     let metadata = std::fs::metadata(output)?;
     Ok(metadata.len())
 }
+
+#[cfg(test)]
+pub struct FakeEncoder {
+    pub info: VideoInfo,
+    pub motion: Vec<MotionSample>,
+    pub crop: Option<Crop>,
+    pub content: ContentStats,
+    pub bytes_per_second: f64,
+    pub saved_bytes: u64,
+    pub quality_of: fn(&EncodeParams) -> f64,
+    pub calls: std::sync::Mutex<Vec<EncodeParams>>,
+}
+
+#[cfg(test)]
+impl FakeEncoder {
+    pub fn new(duration_secs: f64, bytes_per_second: f64) -> Self {
+        Self {
+            info: VideoInfo {
+                width: 960,
+                height: 540,
+                duration_secs,
+                framerate: 30.0,
+            },
+            motion: Vec::new(),
+            crop: None,
+            content: ContentStats {
+                colors: 4096,
+                motion_fraction: 0.05,
+                edge_density: 0.02,
+            },
+            bytes_per_second,
+            saved_bytes: 0,
+            quality_of: FakeEncoder::quality,
+            calls: std::sync::Mutex::new(Vec::new()),
+        }
+    }
+
+    pub fn size_of(&self, params: &EncodeParams) -> u64 {
+        let width_factor = (params.width as f64 / 480.0).powi(2);
+        let fps_factor = params.fps as f64 / 15.0;
+        let color_factor = (params.colors.max(2) as f64).log2() / 8.0;
+        let lossy_factor = 1.0 + params.lossy as f64 / 40.0;
+        (self.bytes_per_second * params.output_secs() * width_factor * fps_factor * color_factor
+            / lossy_factor) as u64
+    }
+
+    pub fn quality(params: &EncodeParams) -> f64 {
+        let width_loss = 1.0 - params.width as f64 / 480.0;
+        let fps_loss = 1.0 - params.fps as f64 / 15.0;
+        let color_loss = 1.0 - (params.colors.max(2) as f64).log2() / 8.0;
+        1.0 - 0.2 * (width_loss + fps_loss + color_loss) - params.lossy as f64 / 1000.0
+    }
+
+    pub fn calls(&self) -> Vec<EncodeParams> {
+        self.calls.lock().unwrap().clone()
+    }
+}
+
+#[cfg(test)]
+impl Encoder for FakeEncoder {
+    fn probe(&self, _input: &Path) -> Result<VideoInfo> {
+        Ok(self.info.clone())
+    }
+
+    fn detect_motion(
+        &self,
+        _input: &Path,
+        _range: &Segment,
+        cancel: &CancelToken,
+    ) -> Result<Vec<MotionSample>> {
+        cancel.check()?;
+        Ok(self.motion.clone())
+    }
+
+    fn detect_crop(
+        &self,
+        _input: &Path,
+        _info: &VideoInfo,
+        _range: &Segment,
+        _cancel: &CancelToken,
+    ) -> Result<Option<Crop>> {
+        Ok(self.crop)
+    }
+
+    fn analyze_content(
+        &self,
+        _input: &Path,
+        _range: &Segment,
+        _crop: Option<Crop>,
+        _cancel: &CancelToken,
+    ) -> Result<ContentStats> {
+        Ok(self.content)
+    }
+
+    fn encode(
+        &self,
+        _input: &Path,
+        output: &Path,
+        params: &EncodeParams,
+        cancel: &CancelToken,
+    ) -> Result<u64> {
+        cancel.check()?;
+        self.calls.lock().unwrap().push(params.clone());
+        std::fs::write(output, params.start_secs.to_string())?;
+        Ok(self.size_of(params))
+    }
+
+    fn optimize(
+        &self,
+        _output: &Path,
+        _params: &EncodeParams,
+        _cancel: &CancelToken,
+    ) -> Result<u64> {
+        Ok(self.saved_bytes)
+    }
+
+    fn measure_quality(
+        &self,
+        _input: &Path,
+        _output: &Path,
+        params: &EncodeParams,
+        _reference: &EncodeParams,
+        _cancel: &CancelToken,
+    ) -> Result<f64> {
+        Ok((self.quality_of)(params))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn formats_map_to_file_extensions() {
+        assert_eq!(OutputFormat::Gif.extension(), "gif");
+        assert_eq!(OutputFormat::Webp.extension(), "webp");
+        assert_eq!(OutputFormat::WebpLossless.extension(), "webp");
+        assert_eq!(OutputFormat::Mp4.extension(), "mp4");
+        assert_eq!(OutputFormat::Apng.extension(), "png");
+    }
+
+    #[test]
+    fn apng_defaults_to_lossless_color() {
+        assert_eq!(OutputFormat::Apng.default_colors(), TRUE_COLOR);
+        assert_eq!(OutputFormat::Mp4.default_colors(), TRUE_COLOR);
+        assert_eq!(OutputFormat::Gif.default_colors(), 256);
+    }
+
+    fn gif_params(crop: Option<Crop>) -> EncodeParams {
+        EncodeParams {
+            format: OutputFormat::Gif,
+            width: 480,
+            fps: 15,
+            colors: 256,
+            crop,
+            speed: 1.0,
+            start_secs: 0.0,
+            duration_secs: 3.0,
+            skip: Vec::new(),
+            lossy: 0,
+            target_bytes: 0,
+            audio: false,
+        }
+    }
+
+    #[test]
+    fn crop_parses_size_and_offset() {
+        let crop: Crop = "1280x720+640+360".parse().unwrap();
+        assert_eq!(
+            crop,
+            Crop {
+                width: 1280,
+                height: 720,
+                x: 640,
+                y: 360
+            }
+        );
+        let crop: Crop = "800x600".parse().unwrap();
+        assert_eq!((crop.x, crop.y), (0, 0));
+        assert_eq!(crop.to_string(), "800x600+0+0");
+    }
+
+    #[test]
+    fn crop_rejects_malformed_input() {
+        for raw in [
+            "",
+            "1280",
+            "1280x",
+            "0x720+0+0",
+            "1280x720+10",
+            "axb+0+0",
+            "-1x5+0+0",
+        ] {
+            assert!(raw.parse::<Crop>().is_err(), "accepted {raw:?}");
+        }
+    }
+
+    #[test]
+    fn crop_must_fit_inside_frame() {
+        let crop: Crop = "1280x720+640+360".parse().unwrap();
+        assert!(crop.fits_within(1920, 1080));
+        assert!(!crop.fits_within(1920, 1079));
+        assert!(!crop.fits_within(1919, 1080));
+    }
+
+    #[test]
+    fn gif_palette_only_redithers_changed_rectangles() {
+        assert!(
+            palette_filter(&gif_params(None), "floyd_steinberg")
+                .ends_with("paletteuse=dither=floyd_steinberg:diff_mode=rectangle")
+        );
+    }
+
+    #[test]
+    fn crop_runs_ahead_of_scale() {
+        let crop = "640x360+10+20".parse().ok();
+        assert!(
+            palette_filter(&gif_params(crop), "none")
+                .starts_with("crop=640:360:10:20,fps=15,mpdecimate,scale=")
+        );
+        assert!(scale_filter(&gif_params(None)).starts_with("fps=15,mpdecimate,scale="));
+    }
+
+    #[test]
+    fn speed_retimes_frames_before_sampling() {
+        let params = EncodeParams {
+            speed: 4.0,
+            ..gif_params("640x360+10+20".parse().ok())
+        };
+        assert!(
+            scale_filter(&params).starts_with("crop=640:360:10:20,setpts=PTS/4,fps=15,mpdecimate,")
+        );
+        assert_eq!(params.output_secs(), 0.75);
+    }
+
+    #[test]
+    fn skipped_gaps_are_selected_out_relative_to_segment_start() {
+        let params = EncodeParams {
+            start_secs: 10.0,
+            duration_secs: 6.0,
+            skip: vec![Segment {
+                start_secs: 11.5,
+                duration_secs: 2.0,
+            }],
+            ..gif_params(None)
+        };
+        assert!(
+            scale_filter(&params)
+                .starts_with("select='not(between(t,1.500,3.500))',setpts=N/FRAME_RATE/TB,fps=15,")
+        );
+        assert_eq!(params.output_secs(), 4.0);
+        assert_eq!(
+            audio_filters(&params).unwrap(),
+            "aselect='not(between(t,1.500,3.500))',asetpts=N/SR/TB"
+        );
+    }
+
+    #[test]
+    fn atempo_chains_stages_within_supported_range() {
+        assert_eq!(atempo_filter(1.5), "atempo=1.5");
+        assert_eq!(atempo_filter(8.0), "atempo=2,atempo=2,atempo=2");
+        assert_eq!(atempo_filter(0.25), "atempo=0.5,atempo=0.5");
+    }
+
+    #[test]
+    fn video_bitrate_fills_target_over_duration() {
+        let target = 10 * 1024 * 1024;
+        let bitrate = video_bitrate_kbps(target, 60.0, false);
+        let expected = (target as f64 * 8.0 * CONTAINER_OVERHEAD / 60_000.0) as u32;
+        assert_eq!(bitrate, expected);
+    }
+
+    #[test]
+    fn video_bitrate_reserves_audio_budget() {
+        let target = 10 * 1024 * 1024;
+        let silent = video_bitrate_kbps(target, 60.0, false);
+        let with_audio = video_bitrate_kbps(target, 60.0, true);
+        assert_eq!(silent - with_audio, AUDIO_BITRATE_KBPS);
+    }
+
+    #[test]
+    fn video_bitrate_has_floor() {
+        assert_eq!(
+            video_bitrate_kbps(1024, 600.0, true),
+            MIN_VIDEO_BITRATE_KBPS
+        );
+    }
+
+    #[test]
+    fn webp_quality_tracks_palette_size() {
+        assert_eq!(webp_quality(256), 80);
+        assert_eq!(webp_quality(128), 60);
+        assert_eq!(webp_quality(32), 45);
+        assert_eq!(webp_quality(1024), 80);
+    }
+}
@@ -22,6 +22,9 @@ pub enum Error {
     #[error("could not reach target size after {0} attempts — video may be too long or complex")]
     TargetUnreachable(u32),
 
+    #[error("conversion cancelled")]
+    Cancelled,
+
     #[error("invalid input: {0}")]
     InvalidInput(String),
 
@@ -0,0 +1,355 @@
+use crate::analyze::ContentClass;
+use crate::cancel::CancelToken;
+use crate::encode::{Backend, Crop, EncodeParams, Encoder, FfmpegEncoder, OutputFormat};
+use crate::error::{Error, Result};
+use crate::native::NativeEncoder;
+use crate::probe::VideoInfo;
+use crate::progress::ProgressEvent;
+use crate::workdir::WorkDir;
+use crate::{ConvertOptions, MIN_SPLIT_DURATION, Plan, Segment, idle, strategy};
+use serde::Serialize;
+use std::fmt;
+use std::path::PathBuf;
+
+const SAMPLE_WINDOWS: usize = 3;
+const SAMPLE_SECS: f64 = 1.0;
+const BYTES_PER_MB: f64 = 1024.0 * 1024.0;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PlannedChunk {
+    pub start_secs: f64,
+    pub duration_secs: f64,
+    pub width: u32,
+    pub fps: u32,
+    pub colors: u32,
+    pub lossy: u32,
+    pub bytes: u64,
+    pub reductions: u32,
+    pub degraded: bool,
+    pub fits: bool,
+}
+
+impl PlannedChunk {
+    fn new(params: &EncodeParams, bytes: u64, reductions: u32, degraded: bool) -> Self {
+        Self {
+            start_secs: params.start_secs,
+            duration_secs: params.duration_secs,
+            width: params.width,
+            fps: params.fps,
+            colors: params.colors,
+            lossy: params.lossy,
+            bytes,
+            reductions,
+            degraded,
+            fits: bytes <= params.target_bytes,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Estimate {
+    pub input: PathBuf,
+    pub source: VideoInfo,
+    pub format: OutputFormat,
+    pub crop: Option<Crop>,
+    pub content: ContentClass,
+    pub speed: f64,
+    pub removed_secs: f64,
+    pub max_bytes: u64,
+    pub sampled_secs: f64,
+    pub bytes_per_second: f64,
+    pub chunks: Vec<PlannedChunk>,
+}
+
+impl Estimate {
+    pub fn total_bytes(&self) -> u64 {
+        self.chunks.iter().map(|chunk| chunk.bytes).sum()
+    }
+
+    pub fn to_json(&self) -> Result<String> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+}
+
+impl fmt::Display for Estimate {
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            formatter,
+            "plan for {} ({}, content: {})",
+            self.input.display(),
+            self.format.extension(),
+            self.content
+        )?;
+        write!(
+            formatter,
+            "\n  sampled {:.1}s of output: {:.2} MB per second",
+            self.sampled_secs,
+            self.bytes_per_second / BYTES_PER_MB
+        )?;
+        for (index, chunk) in self.chunks.iter().enumerate() {
+            write!(
+                formatter,
+                "\n  {}. {:.1}s - {:.1}s: ~{:.2} MB at {}px, {}fps, {} colors",
+                index + 1,
+                chunk.start_secs,
+                chunk.start_secs + chunk.duration_secs,
+                chunk.bytes as f64 / BYTES_PER_MB,
+                chunk.width,
+                chunk.fps,
+                chunk.colors
+            )?;
+            if chunk.lossy > 0 {
+                write!(formatter, ", lossy {}", chunk.lossy)?;
+            }
+            if !chunk.fits {
+                write!(
+                    formatter,
+                    " (over the {:.2} MB limit even at minimum settings)",
+                    self.max_bytes as f64 / BYTES_PER_MB
+                )?;
+            } else if chunk.degraded {
+                write!(formatter, " (degraded, {} reduction(s))", chunk.reductions)?;
+            }
+        }
+        write!(
+            formatter,
+            "\n{} chunk(s), ~{:.2} MB total",
+            self.chunks.len(),
+            self.total_bytes() as f64 / BYTES_PER_MB
+        )
+    }
+}
+
+fn sample_windows(
+    segments: &[Segment],
+    gaps: &[Segment],
+    window_secs: f64,
+) -> Vec<(Segment, Segment)> {
+    let count = segments.len().min(SAMPLE_WINDOWS);
+    (0..count)
+        .map(|sample| &segments[(2 * sample + 1) * segments.len() / (2 * count)])
+        .map(|segment| {
+            let kept = idle::kept_secs(segment, gaps);
+            let wanted = window_secs.min(kept);
+            let start_secs =
+                idle::source_time_after(segment.start_secs, (kept - wanted) / 2.0, gaps);
+            let end_secs = idle::source_time_after(segment.start_secs, (kept + wanted) / 2.0, gaps);
+            let window = Segment {
+                start_secs,
+                duration_secs: end_secs - start_secs,
+            };
+            (*segment, window)
+        })
+        .collect()
+}
+
+pub fn estimate(
+    options: &ConvertOptions,
+    cancel: &CancelToken,
+    on_progress: impl FnMut(&ProgressEvent),
+) -> Result<Estimate> {
+    crate::check_backend(options)?;
+
+    match options.backend {
+        Backend::Ffmpeg => estimate_with(options, &FfmpegEncoder, cancel, on_progress),
+        Backend::Native => estimate_with(options, &NativeEncoder::default(), cancel, on_progress),
+    }
+}
+
+pub fn estimate_with(
+    options: &ConvertOptions,
+    encoder: &impl Encoder,
+    cancel: &CancelToken,
+    mut on_progress: impl FnMut(&ProgressEvent),
+) -> Result<Estimate> {
+    let Plan {
+        info,
+        crop,
+        content,
+        removed_secs,
+        initial,
+        target_bytes,
+        output_stem,
+        mut segments,
+    } = crate::plan(options, encoder, cancel, &mut on_progress)?;
+
+    let work_dir =
+        WorkDir::create(&std::env::temp_dir().join(output_stem.file_name().unwrap_or_default()))?;
+
+    let mut sampled_secs = 0.0;
+    let mut sampled_bytes = 0;
+    for (index, (segment, window)) in sample_windows(
+        segments.make_contiguous(),
+        &initial.gaps,
+        SAMPLE_SECS * options.speed,
+    )
+    .iter()
+    .enumerate()
+    {
+        let temp_path = crate::temp_output_path(work_dir.path(), index as u32, options.format);
+        let chunk_secs = initial.encode_params(segment, target_bytes).output_secs();
+        let mut params = initial.encode_params(window, target_bytes);
+        params.target_bytes = (target_bytes as f64 * params.output_secs() / chunk_secs) as u64;
+        let encoded = encoder.encode(&options.input, &temp_path, &params, cancel)?;
+        let saved = encoder.optimize(&temp_path, &params, cancel)?;
+        let size = encoded.saturating_sub(saved);
+        on_progress(&ProgressEvent::SampleEncoded {
+            window: *window,
+            size,
+        });
+        sampled_secs += params.output_secs();
+        sampled_bytes += size;
+    }
+    if sampled_secs <= 0.0 {
+        return Err(Error::InvalidInput(
+            "nothing left to sample in the selected range".into(),
+        ));
+    }
+    let bytes_per_second = sampled_bytes as f64 / sampled_secs;
+
+    let mut pending = segments;
+    let mut chunks = Vec::new();
+    while let Some(segment) = pending.pop_front() {
+        let params = initial.encode_params(&segment, target_bytes);
+        let bytes = (bytes_per_second * params.output_secs()) as u64;
+
+        if bytes <= target_bytes {
+            chunks.push(PlannedChunk::new(&params, bytes, 0, false));
+        } else if params.output_secs() > MIN_SPLIT_DURATION {
+            let (first, second) = crate::split_in_half(&segment, &initial.gaps);
+            pending.push_front(second);
+            pending.push_front(first);
+        } else {
+            let prediction = strategy::predict_ladder(&initial, &segment, bytes, target_bytes);
+            chunks.push(PlannedChunk::new(
+                &prediction.params,
+                prediction.bytes,
+                prediction.reductions,
+                true,
+            ));
+        }
+    }
+
+    Ok(Estimate {
+        input: options.input.clone(),
+        source: info,
+        format: options.format,
+        crop,
+        content,
+        speed: options.speed,
+        removed_secs,
+        max_bytes: target_bytes,
+        sampled_secs,
+        bytes_per_second,
+        chunks,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::encode::FakeEncoder;
+    use crate::tests::{test_dir, test_options};
+
+    #[test]
+    fn windows_are_centred_in_spread_out_segments() {
+        let segments: Vec<Segment> = (0..5)
+            .map(|index| Segment {
+                start_secs: index as f64 * 3.0,
+                duration_secs: 3.0,
+            })
+            .collect();
+
+        let windows = sample_windows(&segments, &[], 1.0);
+
+        let starts: Vec<f64> = windows
+            .iter()
+            .map(|(_, window)| window.start_secs)
+            .collect();
+        assert_eq!(starts, vec![1.0, 7.0, 13.0]);
+        assert!(
+            windows
+                .iter()
+                .all(|(_, window)| window.duration_secs == 1.0)
+        );
+        assert_eq!(windows[1].0, segments[2]);
+    }
+
+    #[test]
+    fn windows_cover_kept_time_around_gaps() {
+        let segments = [Segment {
+            start_secs: 0.0,
+            duration_secs: 4.0,
+        }];
+        let gaps = [Segment {
+            start_secs: 1.0,
+            duration_secs: 2.0,
+        }];
+
+        let (_, window) = sample_windows(&segments, &gaps, 1.0)[0];
+
+        assert_eq!(window.start_secs, 0.5);
+        assert_eq!(window.duration_secs, 3.0);
+        assert_eq!(idle::kept_secs(&window, &gaps), 1.0);
+    }
+
+    #[test]
+    fn estimate_keeps_chunks_that_fit_without_writing_output() {
+        let dir = test_dir("estimate-fit");
+        let options = test_options(&dir, 3.0);
+        let encoder = FakeEncoder::new(9.0, 1000.0);
+
+        let estimate = estimate_with(&options, &encoder, &CancelToken::new(), |_| {}).unwrap();
+
+        assert_eq!(estimate.chunks.len(), 3);
+        assert!(
+            estimate
+                .chunks
+                .iter()
+                .all(|chunk| chunk.fits && !chunk.degraded)
+        );
+        assert_eq!(estimate.chunks[0].bytes, 3000);
+        assert_eq!(encoder.calls().len(), 3);
+        assert!(encoder.calls().iter().all(|call| call.duration_secs == 1.0));
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 0);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn mp4_samples_target_their_share_of_the_chunk() {
+        let dir = test_dir("estimate-mp4");
+        let mut options = test_options(&dir, 3.0);
+        options.format = OutputFormat::Mp4;
+        let encoder = FakeEncoder::new(9.0, 1000.0);
+
+        let estimate = estimate_with(&options, &encoder, &CancelToken::new(), |_| {}).unwrap();
+        let calls = encoder.calls();
+        assert_eq!(estimate.chunks.len(), 1);
+        assert_eq!(calls.len(), 1);
+        assert_eq!(calls[0].duration_secs, 1.0);
+        assert_eq!(calls[0].target_bytes, estimate.max_bytes / 9);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn estimate_predicts_splits_and_degradation() {
+        let dir = test_dir("estimate-split");
+        let mut options = test_options(&dir, 3.0);
+        options.max_size_mb = 0.5;
+        let encoder = FakeEncoder::new(3.0, 2.0 * 1024.0 * 1024.0);
+
+        let estimate = estimate_with(&options, &encoder, &CancelToken::new(), |_| {}).unwrap();
+
+        let durations: Vec<f64> = estimate
+            .chunks
+            .iter()
+            .map(|chunk| chunk.duration_secs)
+            .collect();
+        assert_eq!(durations, vec![0.375; 8]);
+        assert!(estimate.chunks.iter().all(|chunk| chunk.degraded));
+        assert!(estimate.chunks.iter().all(|chunk| chunk.fits));
+        assert!(estimate.chunks[0].width < 480);
+        assert!(estimate.total_bytes() <= 8 * estimate.max_bytes);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -1,3 +1,6 @@
+use crate::cancel::CancelToken;
+use crate::encode::{OutputFormat, TRUE_COLOR};
+use crate::progress::ProgressEvent;
 use nightshade::prelude::*;
 use std::path::PathBuf;
 use std::sync::mpsc;
@@ -21,15 +24,21 @@ struct ClipprUi {
     max_size_mb: f64,
     width: u32,
     fps: u32,
-    colors: u32,
+    colors: Option<u32>,
     chunk_secs: f64,
+    format: OutputFormat,
+    jobs: usize,
     log_lines: Vec<String>,
+    progress: f32,
     status: ConversionStatus,
     log_receiver: Option<mpsc::Receiver<LogMessage>>,
+    cancel: Option<CancelToken>,
+    exit_requested: bool,
 }
 
 enum LogMessage {
     Line(String),
+    Progress(f32),
     Finished { success: bool, message: String },
 }
 
@@ -41,11 +50,16 @@ impl Default for ClipprUi {
             max_size_mb: 10.0,
             width: 480,
             fps: 15,
-            colors: 256,
+            colors: None,
             chunk_secs: 3.0,
+            format: OutputFormat::Gif,
+            jobs: crate::available_jobs(),
             log_lines: Vec::new(),
+            progress: 0.0,
             status: ConversionStatus::Idle,
             log_receiver: None,
+            cancel: None,
+            exit_requested: false,
         }
     }
 }
@@ -58,6 +72,7 @@ impl ClipprUi {
         };
 
         self.log_lines.clear();
+        self.progress = 0.0;
         self.status = ConversionStatus::Running;
 
         let options = crate::ConvertOptions {
@@ -70,23 +85,54 @@ impl ClipprUi {
             max_size_mb: self.max_size_mb,
             width: self.width,
             fps: self.fps,
-            colors: self.colors,
+            colors: self.colors.unwrap_or(self.format.default_colors()),
             chunk_secs: self.chunk_secs,
+            start_secs: 0.0,
+            end_secs: None,
+            crop: None,
+            auto_crop: false,
+            speed: 1.0,
+            format: self.format,
+            keep_audio: false,
+            backend: crate::encode::Backend::Ffmpeg,
+            jobs: self.jobs,
+            fit: crate::strategy::FitMode::Ladder,
+            tolerance: 0.1,
+            boundaries: crate::scene::Boundaries::Fixed,
+            snap_window_secs: 1.0,
+            content: None,
+            max_pause_secs: None,
+            manifest: false,
         };
 
         let (sender, receiver) = mpsc::channel();
         self.log_receiver = Some(receiver);
+        let cancel = CancelToken::new();
+        self.cancel = Some(cancel.clone());
 
         std::thread::spawn(move || {
             let progress_sender = sender.clone();
-            let result = crate::convert(&options, |message| {
-                let _ = progress_sender.send(LogMessage::Line(message.to_string()));
+            let mut total_secs = 0.0;
+            let mut done_secs = 0.0;
+            let result = crate::convert(&options, &cancel, |event| {
+                match event {
+                    ProgressEvent::Probed(info) => total_secs = info.duration_secs,
+                    ProgressEvent::SegmentFitted { segment, .. } => {
+                        done_secs += segment.duration_secs;
+                    }
+                    _ => {}
+                }
+                if total_secs > 0.0 {
+                    let fraction = (done_secs / total_secs).min(1.0) as f32;
+                    let _ = progress_sender.send(LogMessage::Progress(fraction));
+                }
+                let _ = progress_sender.send(LogMessage::Line(event.to_string()));
             });
             match result {
-                Ok(paths) => {
+                Ok(report) => {
                     let _ = sender.send(LogMessage::Finished {
                         success: true,
-                        message: format!("conversion complete — {} chunk(s)", paths.len()),
+                        message: format!("conversion complete — {} chunk(s)", report.outputs.len()),
                     });
                 }
                 Err(error) => {
@@ -99,6 +145,12 @@ impl ClipprUi {
         });
     }
 
+    fn cancel_conversion(&mut self) {
+        if let Some(cancel) = &self.cancel {
+            cancel.cancel();
+        }
+    }
+
     fn drain_log_messages(&mut self) {
         let receiver = match &self.log_receiver {
             Some(receiver) => receiver,
@@ -110,6 +162,9 @@ impl ClipprUi {
                 Ok(LogMessage::Line(text)) => {
                     self.log_lines.push(text);
                 }
+                Ok(LogMessage::Progress(fraction)) => {
+                    self.progress = fraction;
+                }
                 Ok(LogMessage::Finished { success, message }) => {
                     self.log_lines.push(message.clone());
                     if success {
@@ -118,6 +173,7 @@ impl ClipprUi {
                         self.status = ConversionStatus::Failed(message);
                     }
                     self.log_receiver = None;
+                    self.cancel = None;
                     return;
                 }
                 Err(mpsc::TryRecvError::Empty) => return,
@@ -132,6 +188,16 @@ impl ClipprUi {
     }
 }
 
+fn format_label(format: OutputFormat) -> &'static str {
+    match format {
+        OutputFormat::Gif => "GIF",
+        OutputFormat::Webp => "WebP",
+        OutputFormat::WebpLossless => "WebP (lossless)",
+        OutputFormat::Mp4 => "MP4",
+        OutputFormat::Apng => "APNG",
+    }
+}
+
 impl State for ClipprUi {
     fn title(&self) -> &str {
         "clippr"
@@ -197,7 +263,14 @@ impl State for ClipprUi {
                     ui.end_row();
 
                     ui.label("Colors:");
-                    ui.add(egui::DragValue::new(&mut self.colors).range(2..=256));
+                    let mut colors = self.colors.unwrap_or(256);
+                    let drag = ui.add_enabled(
+                        self.format != OutputFormat::Mp4,
+                        egui::DragValue::new(&mut colors).range(2..=256),
+                    );
+                    if drag.changed() {
+                        self.colors = Some(colors);
+                    }
                     ui.end_row();
 
                     ui.label("Chunk duration (s):");
@@ -207,6 +280,31 @@ impl State for ClipprUi {
                             .speed(0.1),
                     );
                     ui.end_row();
+
+                    ui.label("Parallel jobs:");
+                    ui.add(egui::DragValue::new(&mut self.jobs).range(1..=256));
+                    ui.end_row();
+
+                    ui.label("Format:");
+                    let previous_format = self.format;
+                    egui::ComboBox::from_id_salt("format_combo")
+                        .selected_text(format_label(self.format))
+                        .show_ui(ui, |ui| {
+                            for format in [
+                                OutputFormat::Gif,
+                                OutputFormat::Webp,
+                                OutputFormat::WebpLossless,
+                                OutputFormat::Mp4,
+                                OutputFormat::Apng,
+                            ] {
+                                ui.selectable_value(&mut self.format, format, format_label(format));
+                            }
+                        });
+                    if self.format != previous_format && self.format.default_colors() == TRUE_COLOR
+                    {
+                        self.colors = None;
+                    }
+                    ui.end_row();
                 });
 
             ui.separator();
@@ -216,7 +314,7 @@ impl State for ClipprUi {
                 ui.text_edit_singleline(&mut self.output_path);
                 if ui.button("Browse...").clicked()
                     && let Some(path) = rfd::FileDialog::new()
-                        .add_filter("GIF", &["gif"])
+                        .add_filter(format_label(self.format), &[self.format.extension()])
                         .save_file()
                 {
                     self.output_path = path.to_string_lossy().into_owned();
@@ -227,10 +325,23 @@ impl State for ClipprUi {
 
             let can_convert = self.input_path.is_some() && self.status != ConversionStatus::Running;
 
-            ui.add_enabled_ui(can_convert, |ui| {
-                if ui.button("Convert").clicked() {
-                    self.start_conversion();
-                }
+            ui.horizontal(|ui| {
+                ui.add_enabled_ui(can_convert, |ui| {
+                    if ui.button("Convert").clicked() {
+                        self.start_conversion();
+                    }
+                });
+
+                let can_cancel = self.status == ConversionStatus::Running
+                    && self
+                        .cancel
+                        .as_ref()
+                        .is_some_and(|cancel| !cancel.is_cancelled());
+                ui.add_enabled_ui(can_cancel, |ui| {
+                    if ui.button("Cancel").clicked() {
+                        self.cancel_conversion();
+                    }
+                });
             });
 
             ui.separator();
@@ -243,6 +354,10 @@ impl State for ClipprUi {
             };
             ui.label(format!("Status: {status_text}"));
 
+            if self.status != ConversionStatus::Idle {
+                ui.add(egui::ProgressBar::new(self.progress).show_percentage());
+            }
+
             if let ConversionStatus::Failed(message) = &self.status {
                 ui.colored_label(egui::Color32::RED, message);
             }
@@ -261,15 +376,18 @@ impl State for ClipprUi {
         });
     }
 
-    fn run_systems(&mut self, _world: &mut World) {
+    fn run_systems(&mut self, world: &mut World) {
         self.drain_log_messages();
-    }
 
-    fn on_keyboard_input(&mut self, world: &mut World, key_code: KeyCode, key_state: KeyState) {
-        if matches!((key_code, key_state), (KeyCode::KeyQ, KeyState::Pressed))
-            && self.status != ConversionStatus::Running
-        {
+        if self.exit_requested && self.status != ConversionStatus::Running {
             world.resources.window.should_exit = true;
         }
     }
+
+    fn on_keyboard_input(&mut self, _world: &mut World, key_code: KeyCode, key_state: KeyState) {
+        if matches!((key_code, key_state), (KeyCode::KeyQ, KeyState::Pressed)) {
+            self.cancel_conversion();
+            self.exit_requested = true;
+        }
+    }
 }
@@ -0,0 +1,164 @@
+use crate::Segment;
+use crate::scene::MotionSample;
+
+const IDLE_SCORE: f64 = 0.003;
+
+fn segment_end(segment: &Segment) -> f64 {
+    segment.start_secs + segment.duration_secs
+}
+
+pub fn idle_gaps(samples: &[MotionSample], range: &Segment, max_pause_secs: f64) -> Vec<Segment> {
+    let range_end = segment_end(range);
+    let mut gaps = Vec::new();
+    let mut still_since = range.start_secs;
+
+    let changes = samples
+        .iter()
+        .filter(|sample| sample.score >= IDLE_SCORE)
+        .map(|sample| sample.time_secs)
+        .filter(|&time| time > range.start_secs && time < range_end)
+        .chain([range_end]);
+
+    for changed_at in changes {
+        let gap_start = still_since + max_pause_secs;
+        if changed_at > gap_start {
+            gaps.push(Segment {
+                start_secs: gap_start,
+                duration_secs: changed_at - gap_start,
+            });
+        }
+        still_since = changed_at;
+    }
+
+    gaps
+}
+
+pub fn gaps_within(segment: &Segment, gaps: &[Segment]) -> Vec<Segment> {
+    let end = segment_end(segment);
+    gaps.iter()
+        .filter_map(|gap| {
+            let start = gap.start_secs.max(segment.start_secs);
+            let gap_end = segment_end(gap).min(end);
+            (gap_end > start).then_some(Segment {
+                start_secs: start,
+                duration_secs: gap_end - start,
+            })
+        })
+        .collect()
+}
+
+pub fn kept_secs(segment: &Segment, gaps: &[Segment]) -> f64 {
+    let skipped: f64 = gaps_within(segment, gaps)
+        .iter()
+        .map(|gap| gap.duration_secs)
+        .sum();
+    segment.duration_secs - skipped
+}
+
+pub fn source_time_after(start_secs: f64, kept_secs: f64, gaps: &[Segment]) -> f64 {
+    let mut time = start_secs;
+    let mut remaining = kept_secs;
+
+    for gap in gaps.iter().filter(|gap| segment_end(gap) > start_secs) {
+        let available = gap.start_secs.max(time) - time;
+        if available >= remaining {
+            return time + remaining;
+        }
+        remaining -= available;
+        time = segment_end(gap);
+    }
+
+    time + remaining
+}
+
+pub fn compressed_cuts(range: &Segment, gaps: &[Segment], chunk_secs: f64) -> Vec<f64> {
+    let range_end = segment_end(range);
+    let mut cuts = Vec::new();
+    let mut previous = range.start_secs;
+
+    loop {
+        let cut = source_time_after(previous, chunk_secs, gaps);
+        if cut >= range_end || kept_secs(&between(cut, range_end), gaps) <= 0.0 {
+            return cuts;
+        }
+        cuts.push(cut);
+        previous = cut;
+    }
+}
+
+fn between(start_secs: f64, end_secs: f64) -> Segment {
+    Segment {
+        start_secs,
+        duration_secs: end_secs - start_secs,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample(time_secs: f64, score: f64) -> MotionSample {
+        MotionSample { time_secs, score }
+    }
+
+    fn spans(segments: &[Segment]) -> Vec<(f64, f64)> {
+        segments
+            .iter()
+            .map(|segment| (segment.start_secs, segment_end(segment)))
+            .collect()
+    }
+
+    #[test]
+    fn idle_runs_keep_only_the_maximum_pause() {
+        let samples = [
+            sample(0.5, 0.2),
+            sample(1.0, 0.0),
+            sample(4.0, 0.0),
+            sample(6.0, 0.1),
+            sample(6.5, 0.1),
+        ];
+        let gaps = idle_gaps(&samples, &between(0.0, 7.0), 1.0);
+        assert_eq!(spans(&gaps), vec![(1.5, 6.0)]);
+    }
+
+    #[test]
+    fn trailing_stillness_is_compressed_too() {
+        let samples = [sample(1.0, 0.5)];
+        let gaps = idle_gaps(&samples, &between(0.0, 10.0), 2.0);
+        assert_eq!(spans(&gaps), vec![(3.0, 10.0)]);
+    }
+
+    #[test]
+    fn short_pauses_are_left_alone() {
+        let samples = [sample(0.5, 0.2), sample(1.2, 0.2), sample(2.0, 0.2)];
+        assert!(idle_gaps(&samples, &between(0.0, 2.5), 1.0).is_empty());
+    }
+
+    #[test]
+    fn kept_time_excludes_overlapping_gaps() {
+        let gaps = [between(2.0, 4.0), between(6.0, 9.0)];
+        assert_eq!(kept_secs(&between(3.0, 7.0), &gaps), 2.0);
+        assert_eq!(
+            spans(&gaps_within(&between(3.0, 7.0), &gaps)),
+            vec![(3.0, 4.0), (6.0, 7.0)]
+        );
+    }
+
+    #[test]
+    fn source_time_skips_over_gaps() {
+        let gaps = [between(2.0, 4.0)];
+        assert_eq!(source_time_after(0.0, 1.0, &gaps), 1.0);
+        assert_eq!(source_time_after(0.0, 3.0, &gaps), 5.0);
+        assert_eq!(source_time_after(3.0, 1.0, &gaps), 5.0);
+    }
+
+    #[test]
+    fn cuts_follow_the_compressed_timeline() {
+        let gaps = [between(2.0, 8.0)];
+        assert_eq!(compressed_cuts(&between(0.0, 12.0), &gaps, 3.0), vec![9.0]);
+        assert_eq!(
+            compressed_cuts(&between(0.0, 9.0), &[], 3.0),
+            vec![3.0, 6.0]
+        );
+    }
+}
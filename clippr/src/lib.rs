@@ -1,14 +1,39 @@
+pub mod analyze;
+pub mod autocrop;
+pub mod batch;
+pub mod cancel;
 pub mod encode;
 pub mod error;
+pub mod estimate;
 pub mod gui;
+pub mod idle;
+mod lzw;
+pub mod native;
+pub mod optimize;
 pub mod probe;
+pub mod progress;
+pub mod quality;
+pub mod report;
+pub mod scene;
 pub mod strategy;
+pub mod timestamp;
+mod workdir;
 
-use encode::EncodeParams;
+use analyze::ContentClass;
+use cancel::CancelToken;
+use encode::{Backend, Crop, Encoder, FfmpegEncoder, OutputFormat};
 use error::{Error, Result};
+use native::NativeEncoder;
+use probe::VideoInfo;
+use progress::ProgressEvent;
+use report::{ChunkReport, ConvertReport};
+use scene::Boundaries;
 use std::collections::VecDeque;
 use std::path::{Path, PathBuf};
-use strategy::InitialParams;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{Condvar, Mutex};
+use strategy::{FitMode, FitTarget, InitialParams};
+use workdir::WorkDir;
 
 const MIN_SPLIT_DURATION: f64 = 0.5;
 
@@ -20,52 +45,232 @@ pub struct ConvertOptions {
     pub fps: u32,
     pub colors: u32,
     pub chunk_secs: f64,
+    pub start_secs: f64,
+    pub end_secs: Option<f64>,
+    pub crop: Option<Crop>,
+    pub auto_crop: bool,
+    pub content: Option<ContentClass>,
+    pub speed: f64,
+    pub format: OutputFormat,
+    pub keep_audio: bool,
+    pub backend: Backend,
+    pub jobs: usize,
+    pub fit: FitMode,
+    pub tolerance: f64,
+    pub boundaries: Boundaries,
+    pub snap_window_secs: f64,
+    pub max_pause_secs: Option<f64>,
+    pub manifest: bool,
 }
 
-#[derive(Clone)]
-struct Segment {
-    start_secs: f64,
-    duration_secs: f64,
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Segment {
+    pub start_secs: f64,
+    pub duration_secs: f64,
 }
 
-fn output_stem_from_args(input: &Path, output: Option<&Path>) -> Result<PathBuf> {
+pub fn available_jobs() -> usize {
+    std::thread::available_parallelism()
+        .map(|count| count.get())
+        .unwrap_or(1)
+}
+
+fn directory_of(path: &Path) -> PathBuf {
+    let parent = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    parent
+        .canonicalize()
+        .unwrap_or_else(|_| parent.to_path_buf())
+}
+
+pub(crate) fn overwrites(existing: &Path, stem: &Path, format: OutputFormat) -> bool {
+    let (Some(name), Some(stem_name)) = (existing.file_name(), stem.file_name()) else {
+        return false;
+    };
+    is_chunk_output_name(
+        &name.to_string_lossy(),
+        &stem_name.to_string_lossy(),
+        format,
+    ) && directory_of(existing) == directory_of(stem)
+}
+
+pub(crate) fn output_stem_from_args(
+    input: &Path,
+    output: Option<&Path>,
+    format: OutputFormat,
+) -> Result<PathBuf> {
     match output {
-        Some(path) => Ok(path.to_path_buf().with_extension("")),
+        Some(path) => {
+            let stem = path.to_path_buf().with_extension("");
+            if overwrites(input, &stem, format) {
+                return Err(Error::InvalidInput(format!(
+                    "output {} would overwrite the input {}",
+                    stem.with_extension(format.extension()).display(),
+                    input.display()
+                )));
+            }
+            Ok(stem)
+        }
         None => {
             let stem = input
                 .file_stem()
                 .ok_or_else(|| Error::InvalidInput("input has no file stem".into()))?;
-            Ok(input.with_file_name(stem))
+            let default = input.with_file_name(stem);
+            if overwrites(input, &default, format) {
+                let mut renamed = stem.to_os_string();
+                renamed.push("_clippr");
+                return Ok(input.with_file_name(renamed));
+            }
+            Ok(default)
         }
     }
 }
 
-fn chunk_output_path(stem: &Path, chunk_index: u32, chunk_count: u32) -> PathBuf {
+fn trim_range(start_secs: f64, end_secs: Option<f64>, duration_secs: f64) -> Result<Segment> {
+    if start_secs < 0.0 {
+        return Err(Error::InvalidInput("--start must not be negative".into()));
+    }
+
+    if start_secs >= duration_secs {
+        return Err(Error::InvalidInput(format!(
+            "--start {start_secs:.2}s is not before the end of the {duration_secs:.2}s video"
+        )));
+    }
+
+    let end_secs = end_secs.unwrap_or(duration_secs);
+    if end_secs > duration_secs {
+        return Err(Error::InvalidInput(format!(
+            "--end {end_secs:.2}s is past the end of the {duration_secs:.2}s video"
+        )));
+    }
+
+    if end_secs <= start_secs {
+        return Err(Error::InvalidInput(format!(
+            "--end {end_secs:.2}s must be after --start {start_secs:.2}s"
+        )));
+    }
+
+    Ok(Segment {
+        start_secs,
+        duration_secs: end_secs - start_secs,
+    })
+}
+
+fn chunk_output_path(
+    stem: &Path,
+    chunk_index: u32,
+    chunk_count: u32,
+    format: OutputFormat,
+) -> PathBuf {
     if chunk_count == 1 {
-        stem.with_extension("gif")
+        stem.with_extension(format.extension())
     } else {
         let name = format!(
-            "{}_{:03}.gif",
+            "{}_{:03}.{}",
             stem.file_name().unwrap_or_default().to_string_lossy(),
             chunk_index + 1,
+            format.extension(),
         );
         stem.with_file_name(name)
     }
 }
 
-fn temp_output_path(stem: &Path, index: u32) -> PathBuf {
-    let name = format!(
-        "{}.tmp_{:06}.gif",
-        stem.file_name().unwrap_or_default().to_string_lossy(),
-        index,
-    );
-    stem.with_file_name(name)
+fn temp_output_path(work_dir: &Path, index: u32, format: OutputFormat) -> PathBuf {
+    work_dir.join(format!("chunk_{:06}.{}", index, format.extension()))
+}
+
+fn is_chunk_output_name(name: &str, stem_name: &str, format: OutputFormat) -> bool {
+    let Some(rest) = name.strip_prefix(stem_name) else {
+        return false;
+    };
+    let Some(rest) = rest.strip_suffix(format.extension()) else {
+        return false;
+    };
+    match rest
+        .strip_prefix('_')
+        .and_then(|rest| rest.strip_suffix('.'))
+    {
+        Some(number) => number.len() >= 3 && number.bytes().all(|byte| byte.is_ascii_digit()),
+        None => rest == ".",
+    }
+}
+
+fn stale_outputs(stem: &Path, format: OutputFormat) -> Vec<PathBuf> {
+    let Ok(manifest) = std::fs::read_to_string(report::manifest_path(stem)) else {
+        return Vec::new();
+    };
+    let Ok(previous) = serde_json::from_str::<serde_json::Value>(&manifest) else {
+        return Vec::new();
+    };
+    let stem_name = stem.file_name().unwrap_or_default().to_string_lossy();
+
+    previous["outputs"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|chunk| Path::new(chunk["path"].as_str()?).file_name())
+        .filter(|name| is_chunk_output_name(&name.to_string_lossy(), &stem_name, format))
+        .map(|name| stem.with_file_name(name))
+        .filter(|path| path.is_file())
+        .collect()
+}
+
+fn check_backend(options: &ConvertOptions) -> Result<()> {
+    if options.backend == Backend::Native && options.format != OutputFormat::Gif {
+        return Err(Error::InvalidInput(
+            "--backend native only supports --format gif".into(),
+        ));
+    }
+    Ok(())
 }
 
 pub fn convert(
     options: &ConvertOptions,
-    mut on_progress: impl FnMut(&str),
-) -> Result<Vec<PathBuf>> {
+    cancel: &CancelToken,
+    on_progress: impl FnMut(&ProgressEvent) + Send,
+) -> Result<ConvertReport> {
+    check_backend(options)?;
+
+    match options.backend {
+        Backend::Ffmpeg => convert_with(options, &FfmpegEncoder, cancel, on_progress),
+        Backend::Native => convert_with(options, &NativeEncoder::default(), cancel, on_progress),
+    }
+}
+
+fn split_in_half(segment: &Segment, gaps: &[Segment]) -> (Segment, Segment) {
+    let kept = idle::kept_secs(segment, gaps);
+    let middle = idle::source_time_after(segment.start_secs, kept / 2.0, gaps);
+    (
+        Segment {
+            start_secs: segment.start_secs,
+            duration_secs: middle - segment.start_secs,
+        },
+        Segment {
+            start_secs: middle,
+            duration_secs: segment.start_secs + segment.duration_secs - middle,
+        },
+    )
+}
+
+pub(crate) struct Plan {
+    info: VideoInfo,
+    crop: Option<Crop>,
+    content: ContentClass,
+    removed_secs: f64,
+    initial: InitialParams,
+    target_bytes: u64,
+    output_stem: PathBuf,
+    segments: VecDeque<Segment>,
+}
+
+pub(crate) fn plan(
+    options: &ConvertOptions,
+    encoder: &impl Encoder,
+    cancel: &CancelToken,
+    on_progress: &mut impl FnMut(&ProgressEvent),
+) -> Result<Plan> {
     if !options.input.exists() {
         return Err(Error::InputNotFound(options.input.clone()));
     }
@@ -78,174 +283,1176 @@ pub fn convert(
         return Err(Error::InvalidInput("--chunk-secs must be positive".into()));
     }
 
-    let info = probe::probe(&options.input)?;
-    on_progress(&format!(
-        "input: {}x{}, {:.1}fps, {:.1}s",
-        info.width, info.height, info.framerate, info.duration_secs
-    ));
+    if options.speed <= 0.0 || !options.speed.is_finite() {
+        return Err(Error::InvalidInput("--speed must be positive".into()));
+    }
+
+    if options
+        .max_pause_secs
+        .is_some_and(|max_pause| max_pause < 0.0)
+    {
+        return Err(Error::InvalidInput(
+            "--max-pause must not be negative".into(),
+        ));
+    }
+
+    if options.snap_window_secs < 0.0 {
+        return Err(Error::InvalidInput(
+            "--snap-window must not be negative".into(),
+        ));
+    }
+
+    if options.jobs == 0 {
+        return Err(Error::InvalidInput("--jobs must be at least 1".into()));
+    }
+
+    if !(0.0..1.0).contains(&options.tolerance) {
+        return Err(Error::InvalidInput(
+            "--tolerance must be between 0 and 1".into(),
+        ));
+    }
+
+    let info = encoder.probe(&options.input)?;
+    on_progress(&ProgressEvent::Probed(info.clone()));
 
     let target_bytes = (options.max_size_mb * 1024.0 * 1024.0) as u64;
-    let output_stem = output_stem_from_args(&options.input, options.output.as_deref())?;
-    let initial_chunk_count = (info.duration_secs / options.chunk_secs).ceil() as u32;
+    let output_stem =
+        output_stem_from_args(&options.input, options.output.as_deref(), options.format)?;
 
-    if initial_chunk_count == 0 {
+    if info.duration_secs <= 0.0 {
         return Err(Error::InvalidInput("video has zero duration".into()));
     }
 
+    let range = trim_range(options.start_secs, options.end_secs, info.duration_secs)?;
+    let chunk_secs = match options.format {
+        OutputFormat::Mp4 => range.duration_secs,
+        _ => options.chunk_secs * options.speed,
+    };
+
+    if options.auto_crop && options.crop.is_some() {
+        return Err(Error::InvalidInput(
+            "--crop and --auto-crop cannot be combined".into(),
+        ));
+    }
+
+    let crop = if options.auto_crop {
+        let detected = encoder.detect_crop(&options.input, &info, &range, cancel)?;
+        on_progress(&ProgressEvent::CropDetected(detected));
+        detected
+    } else {
+        options.crop
+    };
+
+    let source_width = match crop {
+        Some(crop) if !crop.fits_within(info.width, info.height) => {
+            return Err(Error::InvalidInput(format!(
+                "--crop {crop} falls outside the {}x{} video",
+                info.width, info.height
+            )));
+        }
+        Some(crop) => crop.width,
+        None => info.width,
+    };
+
+    let content = match options.content {
+        Some(class) => {
+            on_progress(&ProgressEvent::ContentClassified { class, stats: None });
+            class
+        }
+        None => {
+            let stats = encoder.analyze_content(&options.input, &range, crop, cancel)?;
+            let class = analyze::classify(&stats);
+            on_progress(&ProgressEvent::ContentClassified {
+                class,
+                stats: Some(stats),
+            });
+            class
+        }
+    };
+    let preset = content.preset();
+
+    let mut cuts = scene::fixed_cuts(&range, chunk_secs);
+    let snap_boundaries = options.boundaries == Boundaries::Scene && !cuts.is_empty();
+    let samples = if snap_boundaries || options.max_pause_secs.is_some() {
+        encoder.detect_motion(&options.input, &range, cancel)?
+    } else {
+        Vec::new()
+    };
+
+    let gaps = match options.max_pause_secs {
+        Some(max_pause_secs) => idle::idle_gaps(&samples, &range, max_pause_secs),
+        None => Vec::new(),
+    };
+    let removed_secs: f64 = gaps.iter().map(|gap| gap.duration_secs).sum();
+    if options.max_pause_secs.is_some() {
+        on_progress(&ProgressEvent::IdleCompressed {
+            pauses: gaps.len(),
+            removed_secs,
+        });
+    }
+    if !gaps.is_empty() {
+        cuts = idle::compressed_cuts(&range, &gaps, chunk_secs);
+    }
+
+    let fps = options.fps.min(info.framerate.ceil() as u32);
+    if let Some(max_fps) = preset.max_fps.filter(|&max_fps| max_fps < fps) {
+        on_progress(&ProgressEvent::PresetCapped {
+            class: content,
+            fps: max_fps,
+            requested_fps: fps,
+        });
+    }
+
     let initial = InitialParams {
-        width: options.width.min(info.width),
-        fps: options.fps.min(info.framerate.ceil() as u32),
+        width: options.width.min(source_width),
+        fps: fps.min(preset.max_fps.unwrap_or(u32::MAX)),
         colors: options.colors,
+        crop,
+        speed: options.speed,
+        gaps,
+        format: options.format,
+        audio: options.keep_audio,
+        order: preset.order,
     };
 
-    let mut queue: VecDeque<Segment> = VecDeque::new();
-    for chunk_index in 0..initial_chunk_count {
-        let start_secs = chunk_index as f64 * options.chunk_secs;
-        let remaining = info.duration_secs - start_secs;
-        let duration_secs = remaining.min(options.chunk_secs);
-        if duration_secs > 0.0 {
-            queue.push_back(Segment {
-                start_secs,
-                duration_secs,
-            });
+    if snap_boundaries && !cuts.is_empty() {
+        cuts = scene::snap_cuts(
+            &cuts,
+            &samples,
+            options.snap_window_secs * options.speed,
+            &range,
+        );
+        on_progress(&ProgressEvent::BoundariesSnapped(cuts.clone()));
+    }
+
+    let mut segments: VecDeque<Segment> = VecDeque::new();
+    let mut start_secs = range.start_secs;
+    for end_secs in cuts
+        .into_iter()
+        .chain([range.start_secs + range.duration_secs])
+    {
+        segments.push_back(Segment {
+            start_secs,
+            duration_secs: end_secs - start_secs,
+        });
+        start_secs = end_secs;
+    }
+
+    Ok(Plan {
+        info,
+        crop,
+        content,
+        removed_secs,
+        initial,
+        target_bytes,
+        output_stem,
+        segments,
+    })
+}
+
+pub fn convert_with(
+    options: &ConvertOptions,
+    encoder: &impl Encoder,
+    cancel: &CancelToken,
+    mut on_progress: impl FnMut(&ProgressEvent) + Send,
+) -> Result<ConvertReport> {
+    let Plan {
+        info,
+        crop,
+        content,
+        removed_secs,
+        initial,
+        target_bytes,
+        output_stem,
+        segments,
+    } = plan(options, encoder, cancel, &mut on_progress)?;
+
+    let work_dir = WorkDir::create(&output_stem)?;
+    let job = Job {
+        options,
+        encoder,
+        initial,
+        target: FitTarget {
+            bytes: target_bytes,
+            mode: options.fit,
+            tolerance: options.tolerance,
+        },
+        output_stem,
+        work_dir: work_dir.path(),
+        temp_counter: AtomicU32::new(0),
+        cancel,
+    };
+    let queue = Mutex::new(WorkQueue {
+        pending: segments,
+        in_flight: 0,
+        finished: Vec::new(),
+        error: None,
+    });
+    let ready = Condvar::new();
+    let progress = Mutex::new(&mut on_progress);
+
+    std::thread::scope(|scope| {
+        for _ in 0..options.jobs {
+            scope.spawn(|| job.run_worker(&queue, &ready, &progress));
         }
+    });
+
+    let queue = queue
+        .into_inner()
+        .unwrap_or_else(|error| error.into_inner());
+    if let Some(error) = queue.error {
+        return Err(error);
     }
 
-    let mut temp_paths: Vec<PathBuf> = Vec::new();
-    let mut temp_counter: u32 = 0;
+    let mut outputs = queue.finished;
+    outputs.sort_by(|left, right| left.start_secs.total_cmp(&right.start_secs));
 
-    while let Some(segment) = queue.pop_front() {
-        let temp_path = temp_output_path(&output_stem, temp_counter);
-        temp_counter += 1;
+    let stale = stale_outputs(&job.output_stem, options.format);
 
-        on_progress(&format!(
-            "\nsegment: {:.1}s - {:.1}s ({:.1}s)",
-            segment.start_secs,
-            segment.start_secs + segment.duration_secs,
-            segment.duration_secs,
-        ));
+    let final_count = outputs.len() as u32;
+    for (index, chunk) in outputs.iter_mut().enumerate() {
+        let final_path =
+            chunk_output_path(&job.output_stem, index as u32, final_count, options.format);
+        std::fs::rename(&chunk.path, &final_path)?;
+        chunk.path = final_path;
+    }
 
-        let params = EncodeParams {
-            width: initial.width,
-            fps: initial.fps,
-            colors: initial.colors,
-            start_secs: segment.start_secs,
-            duration_secs: segment.duration_secs,
+    for path in stale {
+        if !outputs.iter().any(|chunk| chunk.path == path) {
+            std::fs::remove_file(path)?;
+        }
+    }
+
+    let report = ConvertReport {
+        input: options.input.clone(),
+        source: info,
+        format: options.format,
+        crop,
+        content,
+        speed: options.speed,
+        removed_secs,
+        max_bytes: target_bytes,
+        saved_bytes: outputs.iter().map(|chunk| chunk.saved_bytes).sum(),
+        outputs,
+    };
+
+    let manifest_path = report::manifest_path(&job.output_stem);
+    if options.manifest {
+        std::fs::write(manifest_path, report.to_json()?)?;
+    } else if manifest_path.exists() {
+        std::fs::remove_file(manifest_path)?;
+    }
+
+    on_progress(&ProgressEvent::Finished(report.paths()));
+
+    Ok(report)
+}
+
+struct WorkQueue {
+    pending: VecDeque<Segment>,
+    in_flight: usize,
+    finished: Vec<ChunkReport>,
+    error: Option<Error>,
+}
+
+enum SegmentOutcome {
+    Encoded(ChunkReport),
+    Split(Segment, Segment),
+}
+
+struct Job<'a, E> {
+    options: &'a ConvertOptions,
+    encoder: &'a E,
+    initial: InitialParams,
+    target: FitTarget,
+    output_stem: PathBuf,
+    work_dir: &'a Path,
+    temp_counter: AtomicU32,
+    cancel: &'a CancelToken,
+}
+
+impl<E: Encoder> Job<'_, E> {
+    fn run_worker(
+        &self,
+        queue: &Mutex<WorkQueue>,
+        ready: &Condvar,
+        progress: &Mutex<impl FnMut(&ProgressEvent)>,
+    ) {
+        let mut on_progress = |event: &ProgressEvent| {
+            let mut callback = progress.lock().unwrap_or_else(|error| error.into_inner());
+            callback(event);
         };
 
-        let size = encode::encode(&options.input, &temp_path, &params)?;
+        loop {
+            let segment = {
+                let mut state = queue.lock().unwrap_or_else(|error| error.into_inner());
+                loop {
+                    if state.error.is_some() {
+                        return;
+                    }
+                    if let Some(segment) = state.pending.pop_front() {
+                        state.in_flight += 1;
+                        break segment;
+                    }
+                    if state.in_flight == 0 {
+                        return;
+                    }
+                    state = ready.wait(state).unwrap_or_else(|error| error.into_inner());
+                }
+            };
 
-        if size <= target_bytes {
-            let size_mb = size as f64 / (1024.0 * 1024.0);
-            on_progress(&format!("  -> {:.2} MB (fits at full quality)", size_mb));
-            temp_paths.push(temp_path);
-            continue;
+            let outcome = self.encode_segment(&segment, &mut on_progress);
+
+            let mut state = queue.lock().unwrap_or_else(|error| error.into_inner());
+            state.in_flight -= 1;
+            match outcome {
+                Ok(SegmentOutcome::Encoded(chunk)) => state.finished.push(chunk),
+                Ok(SegmentOutcome::Split(first, second)) => {
+                    state.pending.push_front(second);
+                    state.pending.push_front(first);
+                }
+                Err(error) => {
+                    if state.error.is_none() {
+                        state.error = Some(error);
+                    }
+                }
+            }
+            ready.notify_all();
         }
+    }
 
-        std::fs::remove_file(&temp_path)?;
+    fn next_temp_path(&self) -> PathBuf {
+        let index = self.temp_counter.fetch_add(1, Ordering::Relaxed);
+        temp_output_path(self.work_dir, index, self.options.format)
+    }
+
+    fn encode_segment(
+        &self,
+        segment: &Segment,
+        on_progress: &mut impl FnMut(&ProgressEvent),
+    ) -> Result<SegmentOutcome> {
+        let temp_path = self.next_temp_path();
 
-        if segment.duration_secs > MIN_SPLIT_DURATION {
-            let half = segment.duration_secs / 2.0;
-            on_progress(&format!(
-                "  -> {:.2} MB (too large, splitting {:.1}s into 2x {:.1}s)",
-                size as f64 / (1024.0 * 1024.0),
-                segment.duration_secs,
-                half,
-            ));
-            queue.push_front(Segment {
-                start_secs: segment.start_secs + half,
-                duration_secs: segment.duration_secs - half,
+        on_progress(&ProgressEvent::SegmentStarted(*segment));
+
+        let params = self.initial.encode_params(segment, self.target.bytes);
+
+        let encoded = self
+            .encoder
+            .encode(&self.options.input, &temp_path, &params, self.cancel)?;
+        let saved = self.encoder.optimize(&temp_path, &params, self.cancel)?;
+        let size = encoded.saturating_sub(saved);
+
+        if size <= self.target.bytes {
+            on_progress(&ProgressEvent::SegmentFitted {
+                segment: *segment,
+                size,
+                saved,
+                degraded: false,
             });
-            queue.push_front(Segment {
-                start_secs: segment.start_secs,
-                duration_secs: half,
+            return Ok(SegmentOutcome::Encoded(ChunkReport::new(
+                temp_path, &params, size, saved, 1, false,
+            )));
+        }
+
+        std::fs::remove_file(&temp_path)?;
+
+        if params.output_secs() > MIN_SPLIT_DURATION {
+            let halves = split_in_half(segment, &self.initial.gaps);
+            on_progress(&ProgressEvent::SegmentSplit {
+                segment: *segment,
+                size,
+                halves,
             });
-            continue;
+            return Ok(SegmentOutcome::Split(halves.0, halves.1));
         }
 
-        on_progress(&format!(
-            "  -> {:.2} MB (too large, segment too short to split — degrading quality)",
-            size as f64 / (1024.0 * 1024.0),
-        ));
+        on_progress(&ProgressEvent::SegmentDegrading {
+            segment: *segment,
+            size,
+        });
 
-        let temp_path = temp_output_path(&output_stem, temp_counter);
-        temp_counter += 1;
+        let temp_path = self.next_temp_path();
 
-        let size = strategy::auto_encode(
-            &options.input,
+        let fitted = strategy::auto_encode(
+            self.encoder,
+            &self.options.input,
             &temp_path,
-            target_bytes,
-            &initial,
-            segment.start_secs,
-            segment.duration_secs,
-            &mut on_progress,
+            &self.target,
+            &self.initial,
+            segment,
+            self.cancel,
+            on_progress,
         )?;
 
-        let size_mb = size as f64 / (1024.0 * 1024.0);
-        on_progress(&format!("  -> {:.2} MB (degraded quality)", size_mb));
-        temp_paths.push(temp_path);
+        on_progress(&ProgressEvent::SegmentFitted {
+            segment: *segment,
+            size: fitted.bytes,
+            saved: fitted.saved_bytes,
+            degraded: true,
+        });
+        Ok(SegmentOutcome::Encoded(ChunkReport {
+            ssim: fitted.ssim,
+            ..ChunkReport::new(
+                temp_path,
+                &fitted.params,
+                fitted.bytes,
+                fitted.saved_bytes,
+                fitted.attempts + 1,
+                true,
+            )
+        }))
     }
+}
 
-    let final_count = temp_paths.len() as u32;
-    let mut outputs: Vec<PathBuf> = Vec::new();
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use encode::FakeEncoder;
 
-    for (index, temp_path) in temp_paths.iter().enumerate() {
-        let final_path = chunk_output_path(&output_stem, index as u32, final_count);
-        std::fs::rename(temp_path, &final_path)?;
-        outputs.push(final_path);
+    pub(crate) fn test_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("clippr-{}-{name}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
     }
 
-    on_progress(&format!("\ndone — {} chunk(s) written:", outputs.len()));
-    for path in &outputs {
-        on_progress(&format!("  {}", path.display()));
+    pub(crate) fn test_options(dir: &Path, chunk_secs: f64) -> ConvertOptions {
+        ConvertOptions {
+            input: Path::new(env!("CARGO_MANIFEST_DIR")).join("Cargo.toml"),
+            output: Some(dir.join("demo")),
+            max_size_mb: 1.0,
+            width: 480,
+            fps: 15,
+            colors: 256,
+            chunk_secs,
+            start_secs: 0.0,
+            end_secs: None,
+            crop: None,
+            auto_crop: false,
+            content: None,
+            speed: 1.0,
+            format: OutputFormat::Gif,
+            keep_audio: false,
+            backend: Backend::Ffmpeg,
+            jobs: 1,
+            fit: FitMode::Ladder,
+            tolerance: 0.1,
+            boundaries: Boundaries::Fixed,
+            snap_window_secs: 1.0,
+            max_pause_secs: None,
+            manifest: false,
+        }
     }
 
-    Ok(outputs)
-}
+    const MB: f64 = 1024.0 * 1024.0;
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+    #[test]
+    fn convert_keeps_chunks_that_fit() {
+        let dir = test_dir("fit");
+        let encoder = FakeEncoder::new(7.0, 0.1 * MB);
+
+        let outputs = convert_with(
+            &test_options(&dir, 3.0),
+            &encoder,
+            &CancelToken::new(),
+            |_| {},
+        )
+        .unwrap()
+        .paths();
+
+        assert_eq!(outputs.len(), 3);
+        assert_eq!(outputs[2], dir.join("demo_003.gif"));
+        assert!(outputs.iter().all(|path| path.exists()));
+        assert_eq!(encoder.calls().len(), 3);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn mp4_conversion_never_overwrites_the_input() {
+        let dir = test_dir("mp4-input");
+        let input = dir.join("video.mp4");
+        std::fs::write(&input, "source").unwrap();
+        let mut options = test_options(&dir, 3.0);
+        options.input = input.clone();
+        options.output = None;
+        options.format = OutputFormat::Mp4;
+        let encoder = FakeEncoder::new(3.0, 0.1 * MB);
+
+        let outputs = convert_with(&options, &encoder, &CancelToken::new(), |_| {})
+            .unwrap()
+            .paths();
+
+        assert_eq!(outputs, vec![dir.join("video_clippr.mp4")]);
+        assert_eq!(std::fs::read_to_string(&input).unwrap(), "source");
+
+        options.output = Some(input.clone());
+        let result = convert_with(&options, &encoder, &CancelToken::new(), |_| {});
+        assert!(matches!(result, Err(Error::InvalidInput(_))));
+        assert_eq!(encoder.calls().len(), 1);
+        assert_eq!(std::fs::read_to_string(&input).unwrap(), "source");
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn convert_splits_oversized_segments_in_half() {
+        let dir = test_dir("split");
+        let encoder = FakeEncoder::new(3.0, 0.6 * MB);
+
+        let outputs = convert_with(
+            &test_options(&dir, 3.0),
+            &encoder,
+            &CancelToken::new(),
+            |_| {},
+        )
+        .unwrap()
+        .paths();
+
+        let durations: Vec<f64> = encoder
+            .calls()
+            .iter()
+            .map(|params| params.duration_secs)
+            .collect();
+        assert_eq!(durations, vec![3.0, 1.5, 1.5]);
+        assert_eq!(
+            outputs,
+            vec![dir.join("demo_001.gif"), dir.join("demo_002.gif")]
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn convert_counts_optimization_savings_toward_the_target() {
+        let dir = test_dir("optimized");
+        let mut encoder = FakeEncoder::new(3.0, 0.6 * MB);
+        encoder.saved_bytes = MB as u64;
+
+        let report = convert_with(
+            &test_options(&dir, 3.0),
+            &encoder,
+            &CancelToken::new(),
+            |_| {},
+        )
+        .unwrap();
+
+        assert_eq!(encoder.calls().len(), 1);
+        assert_eq!(
+            report.outputs[0].bytes,
+            encoder.size_of(&encoder.calls()[0]) - MB as u64
+        );
+        assert_eq!(report.outputs[0].saved_bytes, MB as u64);
+        assert_eq!(report.saved_bytes, MB as u64);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn convert_orders_parallel_outputs_by_start_time() {
+        let dir = test_dir("parallel");
+        let encoder = FakeEncoder::new(9.0, 0.6 * MB);
+        let options = ConvertOptions {
+            jobs: 4,
+            ..test_options(&dir, 3.0)
+        };
+
+        let outputs = convert_with(&options, &encoder, &CancelToken::new(), |_| {})
+            .unwrap()
+            .paths();
+
+        let starts: Vec<f64> = outputs
+            .iter()
+            .map(|path| std::fs::read_to_string(path).unwrap().parse().unwrap())
+            .collect();
+        assert_eq!(starts, vec![0.0, 1.5, 3.0, 4.5, 6.0, 7.5]);
+        assert_eq!(encoder.calls().len(), 9);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn convert_snaps_scene_boundaries_to_quiet_frames() {
+        let dir = test_dir("scene");
+        let mut encoder = FakeEncoder::new(9.0, 0.1 * MB);
+        encoder.motion = vec![
+            scene::MotionSample {
+                time_secs: 2.6,
+                score: 0.0,
+            },
+            scene::MotionSample {
+                time_secs: 3.0,
+                score: 0.4,
+            },
+            scene::MotionSample {
+                time_secs: 6.5,
+                score: 0.01,
+            },
+        ];
+        let options = ConvertOptions {
+            boundaries: Boundaries::Scene,
+            ..test_options(&dir, 3.0)
+        };
+
+        convert_with(&options, &encoder, &CancelToken::new(), |_| {}).unwrap();
+
+        let segments: Vec<(f64, f64)> = encoder
+            .calls()
+            .iter()
+            .map(|params| (params.start_secs, params.start_secs + params.duration_secs))
+            .collect();
+        assert_eq!(segments, vec![(0.0, 2.6), (2.6, 6.5), (6.5, 9.0)]);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn convert_only_encodes_trimmed_window() {
+        let dir = test_dir("trim");
+        let encoder = FakeEncoder::new(10.0, 0.1 * MB);
+        let options = ConvertOptions {
+            start_secs: 1.5,
+            end_secs: Some(8.0),
+            ..test_options(&dir, 3.0)
+        };
+
+        convert_with(&options, &encoder, &CancelToken::new(), |_| {}).unwrap();
+
+        let segments: Vec<(f64, f64)> = encoder
+            .calls()
+            .iter()
+            .map(|params| (params.start_secs, params.start_secs + params.duration_secs))
+            .collect();
+        assert_eq!(segments, vec![(1.5, 4.5), (4.5, 7.5), (7.5, 8.0)]);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn convert_caps_width_at_crop_width() {
+        let dir = test_dir("crop");
+        let encoder = FakeEncoder::new(3.0, 0.1 * MB);
+        let options = ConvertOptions {
+            crop: "320x200+600+300".parse().ok(),
+            ..test_options(&dir, 3.0)
+        };
+
+        convert_with(&options, &encoder, &CancelToken::new(), |_| {}).unwrap();
+
+        let calls = encoder.calls();
+        assert_eq!(calls[0].width, 320);
+        assert_eq!(calls[0].crop, options.crop);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn convert_applies_detected_crop_to_every_segment() {
+        let dir = test_dir("auto-crop");
+        let mut encoder = FakeEncoder::new(6.0, 0.1 * MB);
+        encoder.crop = "400x300+100+50".parse().ok();
+        let options = ConvertOptions {
+            auto_crop: true,
+            ..test_options(&dir, 3.0)
+        };
+
+        let report = convert_with(&options, &encoder, &CancelToken::new(), |_| {}).unwrap();
+
+        let calls = encoder.calls();
+        assert_eq!(calls.len(), 2);
+        assert!(calls.iter().all(|params| params.crop == encoder.crop));
+        assert_eq!(calls[0].width, 400);
+        assert_eq!(report.crop, encoder.crop);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn convert_rejects_crop_outside_video() {
+        let dir = test_dir("crop-outside");
+        let encoder = FakeEncoder::new(3.0, 0.1 * MB);
+        let options = ConvertOptions {
+            crop: "640x360+400+200".parse().ok(),
+            ..test_options(&dir, 3.0)
+        };
+
+        let result = convert_with(&options, &encoder, &CancelToken::new(), |_| {});
+
+        assert!(matches!(result, Err(Error::InvalidInput(_))));
+        assert!(encoder.calls().is_empty());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn convert_sizes_chunks_in_output_time() {
+        let dir = test_dir("speed");
+        let encoder = FakeEncoder::new(24.0, 0.1 * MB);
+        let options = ConvertOptions {
+            speed: 4.0,
+            ..test_options(&dir, 3.0)
+        };
+
+        let report = convert_with(&options, &encoder, &CancelToken::new(), |_| {}).unwrap();
+
+        let calls = encoder.calls();
+        assert_eq!(calls.len(), 2);
+        assert!(calls.iter().all(|params| params.output_secs() == 3.0));
+        assert_eq!(calls[1].start_secs, 12.0);
+        assert_eq!(report.outputs[1].duration_secs, 12.0);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn convert_chunks_the_idle_compressed_timeline() {
+        let dir = test_dir("idle");
+        let mut encoder = FakeEncoder::new(12.0, 0.1 * MB);
+        encoder.motion = [0.5, 1.0, 9.0, 10.0, 11.0]
+            .into_iter()
+            .map(|time_secs| scene::MotionSample {
+                time_secs,
+                score: 0.2,
+            })
+            .collect();
+        let options = ConvertOptions {
+            max_pause_secs: Some(1.0),
+            ..test_options(&dir, 3.0)
+        };
+
+        let report = convert_with(&options, &encoder, &CancelToken::new(), |_| {}).unwrap();
+
+        let calls = encoder.calls();
+        assert_eq!(report.removed_secs, 7.0);
+        assert_eq!(calls.len(), 2);
+        assert_eq!((calls[0].start_secs, calls[0].duration_secs), (0.0, 10.0));
+        assert_eq!(calls[0].output_secs(), 3.0);
+        assert_eq!(calls[0].skip.len(), 1);
+        assert_eq!(calls[1].output_secs(), 2.0);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn trim_range_defaults_to_whole_video() {
+        let range = trim_range(0.0, None, 10.9).unwrap();
+        assert_eq!((range.start_secs, range.duration_secs), (0.0, 10.9));
+    }
+
+    #[test]
+    fn trim_range_rejects_ranges_outside_video() {
+        assert!(trim_range(11.0, None, 10.9).is_err());
+        assert!(trim_range(0.0, Some(12.0), 10.9).is_err());
+        assert!(trim_range(5.0, Some(5.0), 10.9).is_err());
+        assert!(trim_range(-1.0, None, 10.9).is_err());
+    }
+
+    #[test]
+    fn convert_rejects_zero_jobs() {
+        let dir = test_dir("zero-jobs");
+        let encoder = FakeEncoder::new(3.0, 0.1 * MB);
+        let options = ConvertOptions {
+            jobs: 0,
+            ..test_options(&dir, 3.0)
+        };
+
+        let result = convert_with(&options, &encoder, &CancelToken::new(), |_| {});
+
+        assert!(matches!(result, Err(Error::InvalidInput(_))));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn convert_degrades_segments_too_short_to_split() {
+        let dir = test_dir("degrade");
+        let encoder = FakeEncoder::new(0.5, 3.0 * MB);
+
+        let outputs = convert_with(
+            &test_options(&dir, 0.5),
+            &encoder,
+            &CancelToken::new(),
+            |_| {},
+        )
+        .unwrap()
+        .paths();
+
+        let calls = encoder.calls();
+        assert_eq!(outputs, vec![dir.join("demo.gif")]);
+        assert!(calls.len() > 2);
+        assert!(calls.last().unwrap().width < 480);
+        assert!(calls.iter().all(|params| params.duration_secs == 0.5));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn convert_applies_detected_content_preset() {
+        let dir = test_dir("terminal");
+        let mut encoder = FakeEncoder::new(0.5, 4.0 * MB);
+        encoder.content = analyze::ContentStats {
+            colors: 40,
+            motion_fraction: 0.03,
+            edge_density: 0.2,
+        };
+
+        let mut capped = Vec::new();
+        let report = convert_with(
+            &test_options(&dir, 0.5),
+            &encoder,
+            &CancelToken::new(),
+            |event| {
+                if let ProgressEvent::PresetCapped {
+                    fps, requested_fps, ..
+                } = event
+                {
+                    capped.push((*fps, *requested_fps));
+                }
+            },
+        )
+        .unwrap();
+
+        let calls = encoder.calls();
+        assert_eq!(report.content, ContentClass::Terminal);
+        assert_eq!(capped, vec![(10, 15)]);
+        assert_eq!(calls[0].fps, 10);
+        assert_eq!((calls[2].width, calls[2].fps), (480, 8));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn convert_uses_forced_content_class() {
+        let dir = test_dir("slides");
+        let encoder = FakeEncoder::new(3.0, 0.1 * MB);
+        let options = ConvertOptions {
+            content: Some(ContentClass::Slides),
+            ..test_options(&dir, 3.0)
+        };
+        let mut classified = Vec::new();
+
+        convert_with(&options, &encoder, &CancelToken::new(), |event| {
+            if let ProgressEvent::ContentClassified { class, stats } = event {
+                classified.push((*class, stats.is_some()));
+            }
+        })
+        .unwrap();
+
+        assert_eq!(classified, vec![(ContentClass::Slides, false)]);
+        assert_eq!(encoder.calls()[0].fps, 5);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn convert_records_ssim_of_quality_fitted_chunks() {
+        let dir = test_dir("quality");
+        let encoder = FakeEncoder::new(0.5, 3.0 * MB);
+        let options = ConvertOptions {
+            fit: FitMode::Quality,
+            ..test_options(&dir, 0.5)
+        };
+
+        let report = convert_with(&options, &encoder, &CancelToken::new(), |_| {}).unwrap();
+
+        let chunk = &report.outputs[0];
+        assert!(chunk.degraded);
+        assert!(chunk.bytes <= report.max_bytes);
+        assert!(chunk.ssim.is_some_and(|ssim| ssim > 0.0 && ssim < 1.0));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn convert_cancels_and_removes_temp_files() {
+        let dir = test_dir("cancel");
+        let encoder = FakeEncoder::new(9.0, 0.1 * MB);
+        let cancel = CancelToken::new();
+
+        let result = convert_with(&test_options(&dir, 3.0), &encoder, &cancel, |event| {
+            if let ProgressEvent::SegmentFitted { .. } = event {
+                cancel.cancel();
+            }
+        });
+
+        assert!(matches!(result, Err(Error::Cancelled)));
+        assert_eq!(encoder.calls().len(), 1);
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 0);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn convert_cancels_during_motion_detection() {
+        let dir = test_dir("cancel-motion");
+        let encoder = FakeEncoder::new(9.0, 0.1 * MB);
+        let cancel = CancelToken::new();
+        let options = ConvertOptions {
+            boundaries: Boundaries::Scene,
+            ..test_options(&dir, 3.0)
+        };
+
+        let result = convert_with(&options, &encoder, &cancel, |event| {
+            if let ProgressEvent::ContentClassified { .. } = event {
+                cancel.cancel();
+            }
+        });
+
+        assert!(matches!(result, Err(Error::Cancelled)));
+        assert!(encoder.calls().is_empty());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn convert_writes_manifest_with_chunk_details() {
+        let dir = test_dir("manifest");
+        let encoder = FakeEncoder::new(3.5, 0.6 * MB);
+        let options = ConvertOptions {
+            manifest: true,
+            ..test_options(&dir, 3.0)
+        };
+
+        let report = convert_with(&options, &encoder, &CancelToken::new(), |_| {}).unwrap();
+
+        let manifest = std::fs::read_to_string(dir.join("demo.manifest.json")).unwrap();
+        let value: serde_json::Value = serde_json::from_str(&manifest).unwrap();
+        let chunks = value["outputs"].as_array().unwrap();
+        assert_eq!(chunks.len(), report.outputs.len());
+        assert_eq!(chunks[0]["duration_secs"], 1.5);
+        assert_eq!(chunks[0]["attempts"], 1);
+        assert_eq!(chunks[2]["start_secs"], 3.0);
+        assert_eq!(chunks[2]["degraded"], false);
+        assert_eq!(report.outputs[0].path, dir.join("demo_001.gif"));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn convert_replaces_stale_chunks_from_previous_run() {
+        let dir = test_dir("stale");
+        for name in [
+            "demo.gif",
+            "demo_004.gif",
+            "demo_005.gif",
+            "demo_001.webp",
+            "demo_2024.gif",
+        ] {
+            std::fs::write(dir.join(name), "stale").unwrap();
+        }
+        let previous = serde_json::json!({
+            "outputs": [
+                {"path": dir.join("demo.gif")},
+                {"path": dir.join("demo_004.gif")},
+                {"path": "elsewhere/demo_005.gif"},
+                {"path": dir.join("demo_001.webp")},
+            ]
+        });
+        std::fs::write(dir.join("demo.manifest.json"), previous.to_string()).unwrap();
+        let encoder = FakeEncoder::new(9.0, 0.1 * MB);
+
+        convert_with(
+            &test_options(&dir, 3.0),
+            &encoder,
+            &CancelToken::new(),
+            |_| {},
+        )
+        .unwrap();
+
+        let mut names: Vec<String> = std::fs::read_dir(&dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        names.sort();
+        assert_eq!(
+            names,
+            vec![
+                "demo_001.gif",
+                "demo_001.webp",
+                "demo_002.gif",
+                "demo_003.gif",
+                "demo_2024.gif"
+            ]
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn convert_without_a_manifest_deletes_nothing() {
+        let dir = test_dir("stale-unrecorded");
+        for name in ["demo.gif", "demo_004.gif"] {
+            std::fs::write(dir.join(name), "mine").unwrap();
+        }
+        let encoder = FakeEncoder::new(9.0, 0.1 * MB);
+
+        convert_with(
+            &test_options(&dir, 3.0),
+            &encoder,
+            &CancelToken::new(),
+            |_| {},
+        )
+        .unwrap();
+
+        assert!(dir.join("demo.gif").exists());
+        assert_eq!(std::fs::read(dir.join("demo_004.gif")).unwrap(), b"mine");
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn failed_convert_keeps_previous_outputs_and_no_temp_files() {
+        let dir = test_dir("atomic");
+        std::fs::write(dir.join("demo.gif"), "previous").unwrap();
+        let encoder = FakeEncoder::new(3.0, 1000.0 * MB);
+
+        let result = convert_with(
+            &test_options(&dir, 0.5),
+            &encoder,
+            &CancelToken::new(),
+            |_| {},
+        );
+
+        assert!(result.is_err());
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 1);
+        assert_eq!(
+            std::fs::read_to_string(dir.join("demo.gif")).unwrap(),
+            "previous"
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn convert_refuses_outputs_named_like_the_input_before_encoding() {
+        let dir = test_dir("input-collision");
+        let input = dir.join("demo_002.gif");
+        std::fs::write(&input, "source").unwrap();
+        let encoder = FakeEncoder::new(9.0, 0.1 * MB);
+        let options = ConvertOptions {
+            input: input.clone(),
+            ..test_options(&dir, 3.0)
+        };
+
+        let result = convert_with(&options, &encoder, &CancelToken::new(), |_| {});
+
+        assert!(matches!(result, Err(Error::InvalidInput(_))));
+        assert!(encoder.calls().is_empty());
+        assert_eq!(std::fs::read_to_string(&input).unwrap(), "source");
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn convert_reports_unreachable_target() {
+        let dir = test_dir("unreachable");
+        let encoder = FakeEncoder::new(0.5, 1000.0 * MB);
+
+        let result = convert_with(
+            &test_options(&dir, 0.5),
+            &encoder,
+            &CancelToken::new(),
+            |_| {},
+        );
+
+        assert!(matches!(result, Err(Error::TargetUnreachable(_))));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
 
     #[test]
     fn single_chunk_produces_plain_gif_extension() {
-        let result = chunk_output_path(Path::new("demo"), 0, 1);
+        let result = chunk_output_path(Path::new("demo"), 0, 1, OutputFormat::Gif);
         assert_eq!(result, PathBuf::from("demo.gif"));
     }
 
     #[test]
     fn multi_chunk_produces_numbered_suffixes() {
-        let result = chunk_output_path(Path::new("demo"), 0, 4);
+        let result = chunk_output_path(Path::new("demo"), 0, 4, OutputFormat::Gif);
         assert_eq!(result, PathBuf::from("demo_001.gif"));
 
-        let result = chunk_output_path(Path::new("demo"), 3, 4);
+        let result = chunk_output_path(Path::new("demo"), 3, 4, OutputFormat::Gif);
         assert_eq!(result, PathBuf::from("demo_004.gif"));
     }
 
     #[test]
     fn chunk_path_preserves_parent_directory() {
         let stem = Path::new("/tmp/output/demo");
-        let result = chunk_output_path(stem, 0, 3);
+        let result = chunk_output_path(stem, 0, 3, OutputFormat::Gif);
         assert_eq!(result, PathBuf::from("/tmp/output/demo_001.gif"));
     }
 
+    #[test]
+    fn webp_chunks_use_webp_extension() {
+        let result = chunk_output_path(Path::new("demo"), 0, 1, OutputFormat::Webp);
+        assert_eq!(result, PathBuf::from("demo.webp"));
+
+        let result = chunk_output_path(Path::new("demo"), 1, 2, OutputFormat::WebpLossless);
+        assert_eq!(result, PathBuf::from("demo_002.webp"));
+    }
+
+    #[test]
+    fn temp_path_matches_output_format() {
+        let result = temp_output_path(Path::new(".demo.clippr"), 7, OutputFormat::Webp);
+        assert_eq!(result, Path::new(".demo.clippr").join("chunk_000007.webp"));
+    }
+
+    #[test]
+    fn chunk_output_names_match_only_this_stem_and_format() {
+        assert!(is_chunk_output_name("demo.gif", "demo", OutputFormat::Gif));
+        assert!(is_chunk_output_name(
+            "demo_004.gif",
+            "demo",
+            OutputFormat::Gif
+        ));
+        assert!(is_chunk_output_name(
+            "demo_1234.gif",
+            "demo",
+            OutputFormat::Gif
+        ));
+        assert!(!is_chunk_output_name(
+            "demo_004.webp",
+            "demo",
+            OutputFormat::Gif
+        ));
+        assert!(!is_chunk_output_name(
+            "demo_04.gif",
+            "demo",
+            OutputFormat::Gif
+        ));
+        assert!(!is_chunk_output_name(
+            "demo_abc.gif",
+            "demo",
+            OutputFormat::Gif
+        ));
+        assert!(!is_chunk_output_name(
+            "demo2_001.gif",
+            "demo",
+            OutputFormat::Gif
+        ));
+        assert!(!is_chunk_output_name(
+            "demo.manifest.json",
+            "demo",
+            OutputFormat::Gif
+        ));
+    }
+
     #[test]
     fn output_stem_strips_extension_from_input() {
-        let result = output_stem_from_args(Path::new("video.mp4"), None).unwrap();
+        let result =
+            output_stem_from_args(Path::new("video.mp4"), None, OutputFormat::Gif).unwrap();
         assert_eq!(result, PathBuf::from("video"));
     }
 
     #[test]
     fn output_stem_uses_explicit_output_without_extension() {
-        let result =
-            output_stem_from_args(Path::new("video.mp4"), Some(Path::new("out.gif"))).unwrap();
+        let result = output_stem_from_args(
+            Path::new("video.mp4"),
+            Some(Path::new("out.gif")),
+            OutputFormat::Gif,
+        )
+        .unwrap();
         assert_eq!(result, PathBuf::from("out"));
     }
 
     #[test]
     fn output_stem_explicit_output_no_extension() {
-        let result =
-            output_stem_from_args(Path::new("video.mp4"), Some(Path::new("myoutput"))).unwrap();
+        let result = output_stem_from_args(
+            Path::new("video.mp4"),
+            Some(Path::new("myoutput")),
+            OutputFormat::Gif,
+        )
+        .unwrap();
         assert_eq!(result, PathBuf::from("myoutput"));
     }
+
+    #[test]
+    fn output_stem_moves_aside_from_a_same_format_input() {
+        let result =
+            output_stem_from_args(Path::new("video.mp4"), None, OutputFormat::Mp4).unwrap();
+        assert_eq!(result, PathBuf::from("video_clippr"));
+        assert!(
+            output_stem_from_args(
+                Path::new("clips/take_002.gif"),
+                Some(Path::new("clips/take.gif")),
+                OutputFormat::Gif,
+            )
+            .is_err()
+        );
+    }
 }
@@ -0,0 +1,176 @@
+const MAX_CODES: u16 = 4095;
+const MAX_CODE_WIDTH: u8 = 12;
+
+#[derive(Default)]
+struct BitWriter {
+    bytes: Vec<u8>,
+    buffer: u32,
+    bits: u8,
+}
+
+impl BitWriter {
+    fn write(&mut self, code: u16, width: u8) {
+        self.buffer |= (code as u32) << self.bits;
+        self.bits += width;
+        while self.bits >= 8 {
+            self.bytes.push(self.buffer as u8);
+            self.buffer >>= 8;
+            self.bits -= 8;
+        }
+    }
+
+    fn emit(&mut self, code: u16, width: &mut u8, next: u16) {
+        self.write(code, *width);
+        if next >= 1 << *width && *width < MAX_CODE_WIDTH {
+            *width += 1;
+        }
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        if self.bits > 0 {
+            self.bytes.push(self.buffer as u8);
+        }
+        self.bytes
+    }
+}
+
+pub(crate) fn min_code_size(pixels: &[u8], palette_colors: usize) -> u8 {
+    let colors = pixels
+        .iter()
+        .map(|&pixel| pixel as usize + 1)
+        .max()
+        .unwrap_or(0)
+        .max(palette_colors)
+        .clamp(4, 256);
+    colors.next_power_of_two().trailing_zeros() as u8
+}
+
+pub(crate) fn encode_lossy(
+    pixels: &[u8],
+    min_code_size: u8,
+    limit: u32,
+    distance: impl Fn(u8, u8) -> u32,
+) -> Vec<u8> {
+    let clear = 1u16 << min_code_size;
+    let end = clear + 1;
+    let mut children: Vec<Vec<(u8, u16)>> = vec![Vec::new(); MAX_CODES as usize + 1];
+    let mut next = end + 1;
+    let mut width = min_code_size + 1;
+    let mut writer = BitWriter::default();
+    let mut current: Option<u16> = None;
+
+    writer.write(clear, width);
+    for &pixel in pixels {
+        let Some(code) = current else {
+            current = Some(pixel as u16);
+            continue;
+        };
+
+        let extension = children[code as usize]
+            .iter()
+            .map(|&(index, child)| (distance(index, pixel), index, child))
+            .filter(|&(error, ..)| error <= limit)
+            .min_by_key(|&(error, ..)| error);
+        if let Some((.., child)) = extension {
+            current = Some(child);
+            continue;
+        }
+
+        writer.emit(code, &mut width, next);
+        if next < MAX_CODES {
+            children[code as usize].push((pixel, next));
+            next += 1;
+        } else {
+            writer.emit(clear, &mut width, next);
+            children.iter_mut().for_each(Vec::clear);
+            next = end + 1;
+            width = min_code_size + 1;
+        }
+        current = Some(pixel as u16);
+    }
+
+    if let Some(code) = current {
+        writer.emit(code, &mut width, next);
+    }
+    writer.write(end, width);
+
+    let mut data = vec![min_code_size];
+    data.extend(writer.finish());
+    data
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::borrow::Cow;
+
+    fn noise(length: usize, colors: u32) -> Vec<u8> {
+        let mut state = 0x2545_f491u32;
+        (0..length)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 17;
+                state ^= state << 5;
+                (state % colors) as u8
+            })
+            .collect()
+    }
+
+    fn exact(first: u8, second: u8) -> u32 {
+        if first == second { 0 } else { u32::MAX }
+    }
+
+    fn decode(data: &[u8], width: u16, height: u16, colors: usize) -> Vec<u8> {
+        let palette: Vec<u8> = (0..colors * 3).map(|index| index as u8).collect();
+        let mut file = Vec::new();
+        {
+            let mut encoder = gif::Encoder::new(&mut file, width, height, &palette).unwrap();
+            let frame = gif::Frame {
+                width,
+                height,
+                buffer: Cow::Borrowed(data),
+                ..gif::Frame::default()
+            };
+            encoder.write_lzw_pre_encoded_frame(&frame).unwrap();
+        }
+        let mut decoder = gif::DecodeOptions::new().read_info(&file[..]).unwrap();
+        decoder.read_next_frame().unwrap().unwrap().buffer.to_vec()
+    }
+
+    #[test]
+    fn exact_encoding_round_trips_past_a_full_code_table() {
+        let pixels = noise(200 * 100, 256);
+        let encoded = encode_lossy(&pixels, 8, 0, exact);
+        assert_eq!(decode(&encoded, 200, 100, 256), pixels);
+    }
+
+    #[test]
+    fn small_palettes_use_the_minimum_code_size() {
+        assert_eq!(min_code_size(&[0, 1], 2), 2);
+        assert_eq!(min_code_size(&[0, 9], 4), 4);
+        assert_eq!(min_code_size(&[], 256), 8);
+
+        let pixels = noise(64 * 64, 3);
+        let encoded = encode_lossy(&pixels, 2, 0, exact);
+        assert_eq!(decode(&encoded, 64, 64, 4), pixels);
+    }
+
+    #[test]
+    fn lossy_encoding_stays_within_the_limit_and_shrinks_the_stream() {
+        let pixels = noise(64 * 64, 4);
+        let distance = |first: u8, second: u8| (first / 2).abs_diff(second / 2) as u32;
+
+        let exact = encode_lossy(&pixels, 2, 0, exact);
+        let lossy = encode_lossy(&pixels, 2, 0, distance);
+        let decoded = decode(&lossy, 64, 64, 4);
+
+        assert!(lossy.len() < exact.len());
+        assert_ne!(decoded, pixels);
+        assert!(
+            decoded
+                .iter()
+                .zip(&pixels)
+                .all(|(&kept, &wanted)| distance(kept, wanted) == 0)
+        );
+    }
+}
@@ -1,32 +1,136 @@
 mod cli;
 
 use clap::Parser;
-use cli::Cli;
+use cli::{Cli, Command, ConvertArgs, EncodeArgs, ProbeArgs};
+use clippr::ConvertOptions;
+use clippr::batch;
+use clippr::cancel::CancelToken;
+use serde::Serialize;
+use std::error::Error;
+use std::path::PathBuf;
 
-fn main() {
-    let args = Cli::parse();
+const BYTES_PER_MB: f64 = 1024.0 * 1024.0;
+
+fn run_inputs<T: Serialize>(
+    args: &EncodeArgs,
+    fail_fast: bool,
+    run: impl Fn(ConvertOptions) -> clippr::error::Result<T>,
+    print: impl Fn(&T),
+    describe: impl Fn(&T) -> String,
+) -> Result<(), Box<dyn Error>> {
+    let mut entries = batch::expand_inputs(&args.inputs, args.output_dir.as_deref(), args.format);
+    let inputs: Vec<PathBuf> = entries
+        .iter()
+        .filter(|entry| entry.result.is_ok())
+        .map(|entry| entry.input.clone())
+        .collect();
+    if inputs.len() > 1 && args.output.is_some() {
+        return Err("--output names a single output; use --output-dir with several inputs".into());
+    }
+    batch::check_distinct_outputs(&inputs, args.output_dir.as_deref(), args.format)?;
 
-    if args.gui || args.input.is_none() {
-        if let Err(error) = clippr::gui::run() {
-            eprintln!("error: {error}");
-            std::process::exit(1);
+    if entries.len() == 1 {
+        let entry = entries.remove(0);
+        entry.result?;
+        let value = run(args.options(&entry.input))?;
+        if args.json {
+            println!("{}", serde_json::to_string_pretty(&value)?);
+        } else {
+            print(&value);
         }
-    } else {
-        let input = args.input.unwrap();
-
-        let options = clippr::ConvertOptions {
-            input,
-            output: args.output,
-            max_size_mb: args.max_size_mb,
-            width: args.width,
-            fps: args.fps,
-            colors: args.colors,
-            chunk_secs: args.chunk_secs,
-        };
-
-        if let Err(error) = clippr::convert(&options, |message| eprintln!("{message}")) {
-            eprintln!("error: {error}");
-            std::process::exit(1);
+        return Ok(());
+    }
+
+    let total = entries.len();
+    let summary = batch::run_batch(entries, fail_fast, |index, input| {
+        eprintln!("\n[{}/{total}] {}", index + 1, input.display());
+        let value = run(args.options(input))?;
+        if !args.json {
+            print(&value);
         }
+        Ok(value)
+    });
+
+    eprintln!("\n{}", summary.render(describe));
+    if args.json {
+        let values: Vec<&T> = summary.succeeded().collect();
+        println!("{}", serde_json::to_string_pretty(&values)?);
+    }
+    match summary.failed() {
+        0 => Ok(()),
+        failed => Err(format!("{failed} of {total} input(s) failed").into()),
+    }
+}
+
+fn convert(args: &ConvertArgs) -> Result<(), Box<dyn Error>> {
+    if args.dry_run {
+        return estimate(&args.encode);
+    }
+
+    run_inputs(
+        &args.encode,
+        args.fail_fast,
+        |mut options| {
+            options.manifest = args.manifest;
+            clippr::convert(&options, &CancelToken::new(), |event| eprintln!("{event}"))
+        },
+        |_| {},
+        |report| {
+            let bytes: u64 = report.outputs.iter().map(|chunk| chunk.bytes).sum();
+            format!(
+                "{} chunk(s), {:.2} MB",
+                report.outputs.len(),
+                bytes as f64 / BYTES_PER_MB
+            )
+        },
+    )
+}
+
+fn estimate(args: &EncodeArgs) -> Result<(), Box<dyn Error>> {
+    run_inputs(
+        args,
+        false,
+        |options| {
+            clippr::estimate::estimate(&options, &CancelToken::new(), |event| eprintln!("{event}"))
+        },
+        |estimate| println!("{estimate}"),
+        |estimate| {
+            format!(
+                "~{} chunk(s), ~{:.2} MB",
+                estimate.chunks.len(),
+                estimate.total_bytes() as f64 / BYTES_PER_MB
+            )
+        },
+    )
+}
+
+fn probe(args: &ProbeArgs) -> Result<(), Box<dyn Error>> {
+    let info = clippr::probe::probe(&args.input)?;
+    if args.json {
+        println!("{}", serde_json::to_string_pretty(&info)?);
+    } else {
+        println!(
+            "{}: {}x{}, {:.2}fps, {:.2}s",
+            args.input.display(),
+            info.width,
+            info.height,
+            info.framerate,
+            info.duration_secs
+        );
+    }
+    Ok(())
+}
+
+fn main() {
+    let result = match Cli::parse().into_command() {
+        Command::Convert(args) => convert(&args),
+        Command::Estimate(args) => estimate(&args),
+        Command::Probe(args) => probe(&args),
+        Command::Gui => clippr::gui::run(),
+    };
+
+    if let Err(error) = result {
+        eprintln!("error: {error}");
+        std::process::exit(1);
     }
 }
@@ -0,0 +1,459 @@
+use crate::Segment;
+use crate::cancel::{self, CancelToken};
+use crate::encode::{self, Crop, EncodeParams, Encoder, OutputFormat};
+use crate::error::{Error, Result};
+use crate::probe;
+use color_quant::NeuQuant;
+use std::borrow::Cow;
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::{Arc, Mutex};
+
+const QUANTIZER_SAMPLE_FACTOR: i32 = 10;
+const QUANTIZER_SAMPLE_FRAMES: usize = 8;
+const MAX_CACHED_SEGMENTS: usize = 8;
+
+struct DecodedSegment {
+    input: PathBuf,
+    start_secs: f64,
+    duration_secs: f64,
+    crop: Option<Crop>,
+    speed: f64,
+    skip: Vec<Segment>,
+    width: u32,
+    height: u32,
+    fps: u32,
+    frames: Vec<Vec<u8>>,
+}
+
+impl DecodedSegment {
+    fn covers(&self, input: &Path, params: &EncodeParams) -> bool {
+        self.input == input
+            && self.start_secs == params.start_secs
+            && self.duration_secs == params.duration_secs
+            && self.crop == params.crop
+            && self.speed == params.speed
+            && self.skip == params.skip
+            && self.width >= params.width
+            && self.fps >= params.fps
+    }
+}
+
+#[derive(Default)]
+pub struct NativeEncoder {
+    cache: Mutex<VecDeque<Arc<DecodedSegment>>>,
+}
+
+impl Encoder for NativeEncoder {
+    fn encode(
+        &self,
+        input: &Path,
+        output: &Path,
+        params: &EncodeParams,
+        cancel: &CancelToken,
+    ) -> Result<u64> {
+        if params.format != OutputFormat::Gif {
+            return Err(Error::InvalidInput(
+                "the native backend only writes gif output".into(),
+            ));
+        }
+
+        let segment = self.decoded_segment(input, params, cancel)?;
+        write_gif(&segment, output, params, cancel)?;
+
+        let metadata = std::fs::metadata(output)?;
+        Ok(metadata.len())
+    }
+}
+
+impl NativeEncoder {
+    fn decoded_segment(
+        &self,
+        input: &Path,
+        params: &EncodeParams,
+        cancel: &CancelToken,
+    ) -> Result<Arc<DecodedSegment>> {
+        {
+            let cache = self.cache.lock().unwrap_or_else(|error| error.into_inner());
+            if let Some(segment) = cache.iter().find(|segment| segment.covers(input, params)) {
+                return Ok(Arc::clone(segment));
+            }
+        }
+
+        let segment = Arc::new(decode_segment(input, params, cancel)?);
+
+        let mut cache = self.cache.lock().unwrap_or_else(|error| error.into_inner());
+        cache.retain(|cached| {
+            cached.input != segment.input
+                || cached.start_secs != segment.start_secs
+                || cached.duration_secs != segment.duration_secs
+                || cached.crop != segment.crop
+                || cached.speed != segment.speed
+                || cached.skip != segment.skip
+        });
+        if cache.len() >= MAX_CACHED_SEGMENTS {
+            cache.pop_front();
+        }
+        cache.push_back(Arc::clone(&segment));
+        Ok(segment)
+    }
+}
+
+fn scaled_height(source_width: u32, source_height: u32, width: u32) -> u32 {
+    let height = (source_height as f64 * width as f64 / source_width as f64).round() as u32;
+    (height & !1).max(2)
+}
+
+fn decode_segment(
+    input: &Path,
+    params: &EncodeParams,
+    cancel: &CancelToken,
+) -> Result<DecodedSegment> {
+    let (source_width, source_height) = match params.crop {
+        Some(crop) => (crop.width, crop.height),
+        None => {
+            let info = probe::probe(input)?;
+            (info.width, info.height)
+        }
+    };
+    let width = params.width & !1;
+    let height = scaled_height(source_width, source_height, width);
+
+    let filter = format!(
+        "{source}fps={fps},scale={width}:{height}:flags=lanczos",
+        source = encode::source_filters(params),
+        fps = params.fps,
+    );
+
+    let mut command = Command::new("ffmpeg");
+    command.args(["-v", "error", "-ss", &format!("{:.3}", params.start_secs)]);
+    command.args(["-t", &format!("{:.3}", params.duration_secs)]);
+    command.args(["-i"]);
+    command.arg(input);
+    command.args(["-vf", &filter, "-f", "rawvideo", "-pix_fmt", "rgba", "-"]);
+
+    let result = cancel::run_ffmpeg(&mut command, cancel)?;
+
+    if !result.status.success() {
+        let stderr = String::from_utf8_lossy(&result.stderr);
+        return Err(Error::EncodeFailed(stderr.into_owned()));
+    }
+
+    let frame_bytes = (width * height * 4) as usize;
+    let frames: Vec<Vec<u8>> = result
+        .stdout
+        .chunks_exact(frame_bytes)
+        .map(|frame| frame.to_vec())
+        .collect();
+
+    if frames.is_empty() {
+        return Err(Error::EncodeFailed("ffmpeg decoded no frames".into()));
+    }
+
+    Ok(DecodedSegment {
+        input: input.to_path_buf(),
+        start_secs: params.start_secs,
+        duration_secs: params.duration_secs,
+        crop: params.crop,
+        speed: params.speed,
+        skip: params.skip.clone(),
+        width,
+        height,
+        fps: params.fps,
+        frames,
+    })
+}
+
+fn resample_frame_indices(frame_count: usize, source_fps: u32, fps: u32) -> Vec<usize> {
+    let duration = frame_count as f64 / source_fps as f64;
+    let output_count = ((duration * fps as f64).ceil() as usize).max(1);
+    (0..output_count)
+        .map(|index| {
+            ((index as f64 * source_fps as f64 / fps as f64) as usize).min(frame_count - 1)
+        })
+        .collect()
+}
+
+fn frame_delay(index: usize, fps: u32) -> u16 {
+    let start = (index as f64 * 100.0 / fps as f64).round();
+    let end = ((index + 1) as f64 * 100.0 / fps as f64).round();
+    (end - start) as u16
+}
+
+fn downscale(frame: &[u8], width: u32, height: u32, new_width: u32, new_height: u32) -> Vec<u8> {
+    if width == new_width && height == new_height {
+        return frame.to_vec();
+    }
+
+    let scale_x = width as f64 / new_width as f64;
+    let scale_y = height as f64 / new_height as f64;
+    let mut scaled = Vec::with_capacity((new_width * new_height * 4) as usize);
+
+    for y in 0..new_height {
+        let top = (y as f64 * scale_y) as u32;
+        let bottom = (((y + 1) as f64 * scale_y).ceil() as u32).clamp(top + 1, height);
+        for x in 0..new_width {
+            let left = (x as f64 * scale_x) as u32;
+            let right = (((x + 1) as f64 * scale_x).ceil() as u32).clamp(left + 1, width);
+            let mut sums = [0u32; 4];
+            for source_y in top..bottom {
+                for source_x in left..right {
+                    let offset = ((source_y * width + source_x) * 4) as usize;
+                    for (channel, sum) in sums.iter_mut().enumerate() {
+                        *sum += frame[offset + channel] as u32;
+                    }
+                }
+            }
+            let count = (bottom - top) * (right - left);
+            scaled.extend(sums.iter().map(|sum| (sum / count) as u8));
+        }
+    }
+
+    scaled
+}
+
+fn build_quantizer(frames: &[Vec<u8>], colors: u32) -> NeuQuant {
+    let step = (frames.len() / QUANTIZER_SAMPLE_FRAMES).max(1);
+    let samples: Vec<u8> = frames
+        .iter()
+        .step_by(step)
+        .flat_map(|frame| frame.iter().copied())
+        .collect();
+    NeuQuant::new(
+        QUANTIZER_SAMPLE_FACTOR,
+        colors.clamp(2, 256) as usize,
+        &samples,
+    )
+}
+
+fn merge_repeated_frames(frames: Vec<(Vec<u8>, u16)>) -> Vec<(Vec<u8>, u16)> {
+    let mut merged: Vec<(Vec<u8>, u16)> = Vec::with_capacity(frames.len());
+    for (indices, delay) in frames {
+        match merged.last_mut() {
+            Some((held, held_delay)) if *held == indices => {
+                *held_delay = held_delay.saturating_add(delay);
+            }
+            _ => merged.push((indices, delay)),
+        }
+    }
+    merged
+}
+
+fn write_gif(
+    segment: &DecodedSegment,
+    output: &Path,
+    params: &EncodeParams,
+    cancel: &CancelToken,
+) -> Result<()> {
+    let width = (params.width & !1).min(segment.width);
+    let height = scaled_height(segment.width, segment.height, width);
+
+    let frames: Vec<Vec<u8>> =
+        resample_frame_indices(segment.frames.len(), segment.fps, params.fps)
+            .into_iter()
+            .map(|index| {
+                downscale(
+                    &segment.frames[index],
+                    segment.width,
+                    segment.height,
+                    width,
+                    height,
+                )
+            })
+            .collect();
+
+    let quantizer = build_quantizer(&frames, params.colors);
+    let palette = quantizer.color_map_rgb();
+
+    let file = BufWriter::new(File::create(output)?);
+    let mut encoder = gif::Encoder::new(file, width as u16, height as u16, &palette)
+        .map_err(|error| Error::EncodeFailed(error.to_string()))?;
+    encoder
+        .set_repeat(gif::Repeat::Infinite)
+        .map_err(|error| Error::EncodeFailed(error.to_string()))?;
+
+    let mut indexed = Vec::with_capacity(frames.len());
+    for (index, frame) in frames.iter().enumerate() {
+        cancel.check()?;
+        let indices: Vec<u8> = frame
+            .chunks_exact(4)
+            .map(|pixel| quantizer.index_of(pixel) as u8)
+            .collect();
+        indexed.push((indices, frame_delay(index, params.fps)));
+    }
+
+    for (indices, delay) in merge_repeated_frames(indexed) {
+        let gif_frame = gif::Frame {
+            delay,
+            width: width as u16,
+            height: height as u16,
+            buffer: Cow::Owned(indices),
+            ..gif::Frame::default()
+        };
+        encoder
+            .write_frame(&gif_frame)
+            .map_err(|error| Error::EncodeFailed(error.to_string()))?;
+    }
+
+    let mut file = encoder
+        .into_inner()
+        .map_err(|error| Error::EncodeFailed(error.to_string()))?;
+    file.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scaled_height_keeps_aspect_and_stays_even() {
+        assert_eq!(scaled_height(1920, 1080, 480), 270);
+        assert_eq!(scaled_height(802, 632, 480), 378);
+        assert_eq!(scaled_height(100, 1, 10), 2);
+    }
+
+    #[test]
+    fn resample_keeps_every_frame_at_source_rate() {
+        assert_eq!(resample_frame_indices(4, 15, 15), vec![0, 1, 2, 3]);
+    }
+
+    #[test]
+    fn resample_drops_frames_at_lower_rate() {
+        assert_eq!(resample_frame_indices(6, 30, 10), vec![0, 3]);
+    }
+
+    #[test]
+    fn frame_delays_sum_to_duration() {
+        let total: u32 = (0..15).map(|index| frame_delay(index, 15) as u32).sum();
+        assert_eq!(total, 100);
+    }
+
+    #[test]
+    fn downscale_averages_pixel_blocks() {
+        let frame = [
+            0, 0, 0, 255, 100, 100, 100, 255, //
+            200, 200, 200, 255, 100, 100, 100, 255,
+        ];
+        let scaled = downscale(&frame, 2, 2, 1, 1);
+        assert_eq!(scaled, vec![100, 100, 100, 255]);
+    }
+
+    #[test]
+    fn downscale_same_size_is_identity() {
+        let frame = vec![1, 2, 3, 4];
+        assert_eq!(downscale(&frame, 1, 1, 1, 1), frame);
+    }
+
+    #[test]
+    fn repeated_frames_merge_into_longer_delays() {
+        let frames = vec![
+            (vec![1, 1], 7),
+            (vec![1, 1], 6),
+            (vec![1, 1], 7),
+            (vec![2, 1], 7),
+            (vec![1, 1], 6),
+        ];
+        let merged = merge_repeated_frames(frames);
+        assert_eq!(
+            merged,
+            vec![(vec![1, 1], 20), (vec![2, 1], 7), (vec![1, 1], 6)]
+        );
+    }
+
+    #[test]
+    fn write_gif_produces_decodable_animation() {
+        let red: Vec<u8> = [255, 0, 0, 255].repeat(16);
+        let blue: Vec<u8> = [0, 0, 255, 255].repeat(16);
+        let segment = DecodedSegment {
+            input: PathBuf::from("input.mp4"),
+            start_secs: 0.0,
+            duration_secs: 1.0,
+            crop: None,
+            speed: 1.0,
+            skip: Vec::new(),
+            width: 4,
+            height: 4,
+            fps: 2,
+            frames: vec![red, blue],
+        };
+        let params = EncodeParams {
+            format: OutputFormat::Gif,
+            width: 4,
+            fps: 2,
+            colors: 32,
+            crop: None,
+            speed: 1.0,
+            start_secs: 0.0,
+            duration_secs: 1.0,
+            skip: Vec::new(),
+            lossy: 0,
+            target_bytes: 0,
+            audio: false,
+        };
+        let output = std::env::temp_dir().join(format!("clippr-native-{}.gif", std::process::id()));
+
+        write_gif(&segment, &output, &params, &CancelToken::new()).unwrap();
+
+        let mut options = gif::DecodeOptions::new();
+        options.set_color_output(gif::ColorOutput::RGBA);
+        let mut decoder = options.read_info(File::open(&output).unwrap()).unwrap();
+        let mut frame_count = 0;
+        while let Some(frame) = decoder.read_next_frame().unwrap() {
+            assert_eq!((frame.width, frame.height), (4, 4));
+            assert_eq!(frame.delay, 50);
+            frame_count += 1;
+        }
+        std::fs::remove_file(&output).unwrap();
+        assert_eq!(frame_count, 2);
+    }
+
+    #[test]
+    fn write_gif_holds_still_frames_instead_of_repeating_them() {
+        let red: Vec<u8> = [255, 0, 0, 255].repeat(16);
+        let blue: Vec<u8> = [0, 0, 255, 255].repeat(16);
+        let segment = DecodedSegment {
+            input: PathBuf::from("input.mp4"),
+            start_secs: 0.0,
+            duration_secs: 2.0,
+            crop: None,
+            speed: 1.0,
+            skip: Vec::new(),
+            width: 4,
+            height: 4,
+            fps: 2,
+            frames: vec![red.clone(), red.clone(), red, blue],
+        };
+        let params = EncodeParams {
+            format: OutputFormat::Gif,
+            width: 4,
+            fps: 2,
+            colors: 32,
+            crop: None,
+            speed: 1.0,
+            start_secs: 0.0,
+            duration_secs: 2.0,
+            skip: Vec::new(),
+            lossy: 0,
+            target_bytes: 0,
+            audio: false,
+        };
+        let output =
+            std::env::temp_dir().join(format!("clippr-native-held-{}.gif", std::process::id()));
+
+        write_gif(&segment, &output, &params, &CancelToken::new()).unwrap();
+
+        let mut decoder = gif::DecodeOptions::new()
+            .read_info(File::open(&output).unwrap())
+            .unwrap();
+        let mut delays = Vec::new();
+        while let Some(frame) = decoder.read_next_frame().unwrap() {
+            delays.push(frame.delay);
+        }
+        std::fs::remove_file(&output).unwrap();
+        assert_eq!(delays, vec![150, 50]);
+    }
+}
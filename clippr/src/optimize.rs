@@ -0,0 +1,552 @@
+use crate::cancel::CancelToken;
+use crate::error::{Error, Result};
+use crate::lzw;
+use std::borrow::Cow;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Write};
+use std::path::Path;
+
+struct Animation {
+    width: u16,
+    height: u16,
+    palette: Vec<u8>,
+    repeat: gif::Repeat,
+    frames: Vec<(Vec<u8>, u16)>,
+}
+
+struct Gif {
+    width: u16,
+    height: u16,
+    palette: Vec<u8>,
+    repeat: gif::Repeat,
+    frames: Vec<gif::Frame<'static>>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Bounds {
+    left: usize,
+    top: usize,
+    width: usize,
+    height: usize,
+}
+
+fn decode_error(error: gif::DecodingError) -> Error {
+    Error::EncodeFailed(error.to_string())
+}
+
+fn encode_error(error: gif::EncodingError) -> Error {
+    Error::EncodeFailed(error.to_string())
+}
+
+fn read_animation(path: &Path, cancel: &CancelToken) -> Result<Option<Animation>> {
+    let mut decoder = gif::DecodeOptions::new()
+        .read_info(BufReader::new(File::open(path)?))
+        .map_err(decode_error)?;
+
+    let width = decoder.width();
+    let height = decoder.height();
+    let repeat = decoder.repeat();
+    let Some(palette) = decoder.global_palette().map(<[u8]>::to_vec) else {
+        return Ok(None);
+    };
+
+    let stride = width as usize;
+    let mut canvas = vec![0u8; stride * height as usize];
+    let mut frames = Vec::new();
+
+    while let Some(frame) = decoder.read_next_frame().map_err(decode_error)? {
+        cancel.check()?;
+
+        let local_palette = frame
+            .palette
+            .as_ref()
+            .is_some_and(|local| *local != palette);
+        let restores = !matches!(
+            frame.dispose,
+            gif::DisposalMethod::Any | gif::DisposalMethod::Keep
+        );
+        let outside = frame.left as usize + frame.width as usize > stride
+            || frame.top as usize + frame.height as usize > height as usize;
+        let partial_first = frames.is_empty() && (frame.width, frame.height) != (width, height);
+        if local_palette || restores || outside || partial_first {
+            return Ok(None);
+        }
+
+        for (row, pixels) in frame.buffer.chunks_exact(frame.width as usize).enumerate() {
+            let offset = (frame.top as usize + row) * stride + frame.left as usize;
+            for (target, &pixel) in canvas[offset..offset + pixels.len()].iter_mut().zip(pixels) {
+                if Some(pixel) != frame.transparent {
+                    *target = pixel;
+                }
+            }
+        }
+        frames.push((canvas.clone(), frame.delay));
+    }
+
+    Ok(Some(Animation {
+        width,
+        height,
+        palette,
+        repeat,
+        frames,
+    }))
+}
+
+fn read_gif(path: &Path, cancel: &CancelToken) -> Result<Gif> {
+    let mut decoder = gif::DecodeOptions::new()
+        .read_info(BufReader::new(File::open(path)?))
+        .map_err(decode_error)?;
+
+    let mut frames = Vec::new();
+    while let Some(frame) = decoder.read_next_frame().map_err(decode_error)? {
+        cancel.check()?;
+        frames.push(gif::Frame {
+            interlaced: false,
+            ..frame.clone()
+        });
+    }
+
+    Ok(Gif {
+        width: decoder.width(),
+        height: decoder.height(),
+        palette: decoder
+            .global_palette()
+            .map(<[u8]>::to_vec)
+            .unwrap_or_default(),
+        repeat: decoder.repeat(),
+        frames,
+    })
+}
+
+fn changed_bounds(previous: &[u8], current: &[u8], stride: usize) -> Option<Bounds> {
+    let mut changed = previous
+        .iter()
+        .zip(current)
+        .enumerate()
+        .filter(|(_, (before, after))| before != after)
+        .map(|(index, _)| (index % stride, index / stride));
+
+    let (x, y) = changed.next()?;
+    let (mut left, mut right, mut top, mut bottom) = (x, x, y, y);
+    for (x, y) in changed {
+        left = left.min(x);
+        right = right.max(x);
+        top = top.min(y);
+        bottom = bottom.max(y);
+    }
+
+    Some(Bounds {
+        left,
+        top,
+        width: right - left + 1,
+        height: bottom - top + 1,
+    })
+}
+
+fn delta_frame(
+    previous: &[u8],
+    current: &[u8],
+    stride: usize,
+    bounds: Bounds,
+    palette_colors: usize,
+) -> (Vec<u8>, Option<u8>) {
+    let rows = bounds.top..bounds.top + bounds.height;
+    let row_span = |row: usize| {
+        let start = row * stride + bounds.left;
+        start..start + bounds.width
+    };
+
+    let mut used = [false; 256];
+    for row in rows.clone() {
+        let span = row_span(row);
+        for (&before, &after) in previous[span.clone()].iter().zip(&current[span]) {
+            if before != after {
+                used[after as usize] = true;
+            }
+        }
+    }
+    let transparent = (0..palette_colors.min(256))
+        .find(|&index| !used[index])
+        .map(|index| index as u8);
+
+    let mut buffer = Vec::with_capacity(bounds.width * bounds.height);
+    for row in rows {
+        let span = row_span(row);
+        for (&before, &after) in previous[span.clone()].iter().zip(&current[span]) {
+            buffer.push(match transparent {
+                Some(index) if before == after => index,
+                _ => after,
+            });
+        }
+    }
+    (buffer, transparent)
+}
+
+fn color_distance(palette: &[u8], first: u8, second: u8) -> u32 {
+    let color = |index: u8| palette.get(index as usize * 3..index as usize * 3 + 3);
+    match (color(first), color(second)) {
+        (Some(first), Some(second)) => first
+            .iter()
+            .zip(second)
+            .map(|(&a, &b)| (a as i32 - b as i32).pow(2) as u32)
+            .sum(),
+        _ => u32::MAX,
+    }
+}
+
+fn delta_frames(animation: Animation, cancel: &CancelToken) -> Result<Gif> {
+    let stride = animation.width as usize;
+    let palette_colors = animation.palette.len() / 3;
+    let mut frames: Vec<gif::Frame<'static>> = Vec::with_capacity(animation.frames.len());
+    let mut previous: Option<Vec<u8>> = None;
+
+    for (canvas, delay) in animation.frames {
+        cancel.check()?;
+
+        let Some(before) = previous.as_deref() else {
+            frames.push(gif::Frame {
+                delay,
+                width: animation.width,
+                height: animation.height,
+                buffer: Cow::Owned(canvas.clone()),
+                ..gif::Frame::default()
+            });
+            previous = Some(canvas);
+            continue;
+        };
+
+        match changed_bounds(before, &canvas, stride) {
+            Some(bounds) => {
+                let (buffer, transparent) =
+                    delta_frame(before, &canvas, stride, bounds, palette_colors);
+                frames.push(gif::Frame {
+                    delay,
+                    dispose: gif::DisposalMethod::Keep,
+                    transparent,
+                    left: bounds.left as u16,
+                    top: bounds.top as u16,
+                    width: bounds.width as u16,
+                    height: bounds.height as u16,
+                    buffer: Cow::Owned(buffer),
+                    ..gif::Frame::default()
+                });
+                previous = Some(canvas);
+            }
+            None => {
+                if let Some(held) = frames.last_mut() {
+                    held.delay = held.delay.saturating_add(delay);
+                }
+            }
+        }
+    }
+
+    Ok(Gif {
+        width: animation.width,
+        height: animation.height,
+        palette: animation.palette,
+        repeat: animation.repeat,
+        frames,
+    })
+}
+
+fn lossy_frame(
+    frame: &gif::Frame<'static>,
+    global_palette: &[u8],
+    lossy: u32,
+) -> gif::Frame<'static> {
+    let palette = frame.palette.as_deref().unwrap_or(global_palette);
+    let transparent = frame.transparent;
+    let distance = |first: u8, second: u8| {
+        if first == second {
+            0
+        } else if transparent == Some(first) || transparent == Some(second) {
+            u32::MAX
+        } else {
+            color_distance(palette, first, second)
+        }
+    };
+
+    let min_code_size = lzw::min_code_size(&frame.buffer, palette.len() / 3);
+    let encoded = lzw::encode_lossy(&frame.buffer, min_code_size, lossy * lossy, distance);
+    gif::Frame {
+        buffer: Cow::Owned(encoded),
+        ..frame.clone()
+    }
+}
+
+fn write_gif(path: &Path, gif: &Gif, lossy: u32, cancel: &CancelToken) -> Result<()> {
+    let file = BufWriter::new(File::create(path)?);
+    let mut encoder =
+        gif::Encoder::new(file, gif.width, gif.height, &gif.palette).map_err(encode_error)?;
+    encoder.set_repeat(gif.repeat).map_err(encode_error)?;
+    for frame in &gif.frames {
+        cancel.check()?;
+        if lossy > 0 {
+            let encoded = lossy_frame(frame, &gif.palette, lossy);
+            encoder
+                .write_lzw_pre_encoded_frame(&encoded)
+                .map_err(encode_error)?;
+        } else {
+            encoder.write_frame(frame).map_err(encode_error)?;
+        }
+    }
+    let mut file = encoder.into_inner().map_err(encode_error)?;
+    file.flush()?;
+    Ok(())
+}
+
+pub fn optimize_gif(path: &Path, lossy: u32, cancel: &CancelToken) -> Result<u64> {
+    let gif = match read_animation(path, cancel)? {
+        Some(animation) => delta_frames(animation, cancel)?,
+        None if lossy > 0 => read_gif(path, cancel)?,
+        None => return Ok(0),
+    };
+
+    let optimized = path.with_extension("optimized.gif");
+    if let Err(error) = write_gif(&optimized, &gif, lossy, cancel) {
+        let _ = std::fs::remove_file(&optimized);
+        return Err(error);
+    }
+
+    let original_bytes = std::fs::metadata(path)?.len();
+    let optimized_bytes = std::fs::metadata(&optimized)?.len();
+    if optimized_bytes >= original_bytes {
+        std::fs::remove_file(&optimized)?;
+        return Ok(0);
+    }
+
+    std::fs::rename(&optimized, path)?;
+    Ok(original_bytes - optimized_bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PALETTE: [u8; 12] = [0, 0, 0, 255, 0, 0, 0, 255, 0, 0, 0, 255];
+
+    fn full_frame(buffer: Vec<u8>, delay: u16) -> gif::Frame<'static> {
+        gif::Frame {
+            delay,
+            width: 16,
+            height: 16,
+            buffer: Cow::Owned(buffer),
+            ..gif::Frame::default()
+        }
+    }
+
+    fn write_frames(
+        path: &Path,
+        width: u16,
+        height: u16,
+        palette: &[u8],
+        repeat: gif::Repeat,
+        frames: &[gif::Frame<'static>],
+    ) -> Result<()> {
+        let gif = Gif {
+            width,
+            height,
+            palette: palette.to_vec(),
+            repeat,
+            frames: frames.to_vec(),
+        };
+        write_gif(path, &gif, 0, &CancelToken::new())
+    }
+
+    fn decoded_frames(path: &Path) -> Vec<gif::Frame<'static>> {
+        let mut decoder = gif::DecodeOptions::new()
+            .read_info(File::open(path).unwrap())
+            .unwrap();
+        let mut frames = Vec::new();
+        while let Some(frame) = decoder.read_next_frame().unwrap() {
+            frames.push(frame.clone());
+        }
+        frames
+    }
+
+    #[test]
+    fn bounds_cover_every_changed_pixel() {
+        let previous = vec![0u8; 16];
+        let mut current = previous.clone();
+        current[5] = 1;
+        current[14] = 2;
+        assert_eq!(
+            changed_bounds(&previous, &current, 4),
+            Some(Bounds {
+                left: 1,
+                top: 1,
+                width: 2,
+                height: 3,
+            })
+        );
+        assert_eq!(changed_bounds(&previous, &previous, 4), None);
+    }
+
+    #[test]
+    fn unchanged_pixels_become_transparent_with_an_unused_index() {
+        let previous = vec![0, 0, 0, 1, 1, 1];
+        let current = vec![2, 0, 0, 1, 1, 2];
+        let bounds = changed_bounds(&previous, &current, 3).unwrap();
+        let (buffer, transparent) = delta_frame(&previous, &current, 3, bounds, 4);
+        assert_eq!(transparent, Some(0));
+        assert_eq!(buffer, vec![2, 0, 0, 0, 0, 2]);
+    }
+
+    #[test]
+    fn full_palette_in_use_keeps_opaque_pixels() {
+        let previous = vec![0, 1];
+        let current = vec![1, 0];
+        let bounds = changed_bounds(&previous, &current, 2).unwrap();
+        assert_eq!(
+            delta_frame(&previous, &current, 2, bounds, 2),
+            (vec![1, 0], None)
+        );
+    }
+
+    #[test]
+    fn optimized_gif_stores_only_changed_rectangles() {
+        let path = std::env::temp_dir().join(format!("clippr-optimize-{}.gif", std::process::id()));
+        let background: Vec<u8> = (0..256).map(|index| (index % 3) as u8).collect();
+        let mut moved = background.clone();
+        moved[5 * 16 + 6] = 3;
+        moved[6 * 16 + 7] = 3;
+        let frames = [
+            full_frame(background.clone(), 10),
+            full_frame(moved.clone(), 10),
+            full_frame(moved, 10),
+            full_frame(background, 10),
+        ];
+        write_frames(&path, 16, 16, &PALETTE, gif::Repeat::Infinite, &frames).unwrap();
+
+        let saved = optimize_gif(&path, 0, &CancelToken::new()).unwrap();
+        let optimized = decoded_frames(&path);
+        std::fs::remove_file(&path).unwrap();
+
+        assert!(saved > 0);
+        let layout: Vec<_> = optimized
+            .iter()
+            .map(|frame| {
+                (
+                    frame.left,
+                    frame.top,
+                    frame.width,
+                    frame.height,
+                    frame.delay,
+                )
+            })
+            .collect();
+        assert_eq!(
+            layout,
+            vec![(0, 0, 16, 16, 10), (6, 5, 2, 2, 20), (6, 5, 2, 2, 10)]
+        );
+        assert!(optimized[1].transparent.is_some());
+    }
+
+    const NEAR_PALETTE: [u8; 12] = [0, 0, 0, 4, 4, 4, 200, 0, 0, 204, 0, 0];
+
+    fn noise(seed: u32) -> Vec<u8> {
+        let mut state = seed;
+        (0..256)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 17;
+                state ^= state << 5;
+                (state % 4) as u8
+            })
+            .collect()
+    }
+
+    fn assert_within(frames: &[gif::Frame<'static>], originals: &[Vec<u8>], lossy: u32) {
+        for (frame, original) in frames.iter().zip(originals) {
+            assert!(
+                frame
+                    .buffer
+                    .iter()
+                    .zip(original)
+                    .all(
+                        |(&kept, &wanted)| color_distance(&NEAR_PALETTE, kept, wanted)
+                            <= lossy * lossy
+                    )
+            );
+        }
+    }
+
+    #[test]
+    fn lossy_shrinks_gifs_the_delta_pass_cannot_handle() {
+        let path = std::env::temp_dir().join(format!(
+            "clippr-optimize-lossy-dispose-{}.gif",
+            std::process::id()
+        ));
+        let originals = [noise(1), noise(2)];
+        let frames = [
+            full_frame(originals[0].clone(), 10),
+            gif::Frame {
+                dispose: gif::DisposalMethod::Background,
+                palette: Some(NEAR_PALETTE.to_vec()),
+                ..full_frame(originals[1].clone(), 10)
+            },
+        ];
+        write_frames(&path, 16, 16, &NEAR_PALETTE, gif::Repeat::Infinite, &frames).unwrap();
+
+        let saved = optimize_gif(&path, 40, &CancelToken::new()).unwrap();
+        let optimized = decoded_frames(&path);
+        std::fs::remove_file(&path).unwrap();
+
+        assert!(saved > 0);
+        assert_eq!(optimized.len(), 2);
+        assert_eq!(optimized[1].dispose, gif::DisposalMethod::Background);
+        assert_within(&optimized, &originals, 40);
+    }
+
+    #[test]
+    fn lossy_applies_on_top_of_delta_frames() {
+        let lossless = std::env::temp_dir().join(format!(
+            "clippr-optimize-lossless-{}.gif",
+            std::process::id()
+        ));
+        let lossy = lossless.with_extension("lossy.gif");
+        let originals = [noise(3), noise(4)];
+        let frames: Vec<_> = originals
+            .iter()
+            .map(|buffer| full_frame(buffer.clone(), 10))
+            .collect();
+        for path in [&lossless, &lossy] {
+            write_frames(path, 16, 16, &NEAR_PALETTE, gif::Repeat::Infinite, &frames).unwrap();
+        }
+
+        optimize_gif(&lossless, 0, &CancelToken::new()).unwrap();
+        optimize_gif(&lossy, 40, &CancelToken::new()).unwrap();
+        let lossless_bytes = std::fs::metadata(&lossless).unwrap().len();
+        let lossy_bytes = std::fs::metadata(&lossy).unwrap().len();
+        let optimized = decoded_frames(&lossy);
+        std::fs::remove_file(&lossless).unwrap();
+        std::fs::remove_file(&lossy).unwrap();
+
+        assert!(lossy_bytes < lossless_bytes);
+        assert_within(&optimized[..1], &originals, 40);
+    }
+
+    #[test]
+    fn restoring_disposal_is_left_untouched() {
+        let path = std::env::temp_dir().join(format!(
+            "clippr-optimize-dispose-{}.gif",
+            std::process::id()
+        ));
+        let frames = [
+            full_frame(vec![0; 256], 10),
+            gif::Frame {
+                dispose: gif::DisposalMethod::Background,
+                ..full_frame(vec![1; 256], 10)
+            },
+        ];
+        write_frames(&path, 16, 16, &PALETTE, gif::Repeat::Infinite, &frames).unwrap();
+        let before = std::fs::read(&path).unwrap();
+
+        let saved = optimize_gif(&path, 0, &CancelToken::new()).unwrap();
+        let after = std::fs::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(saved, 0);
+        assert_eq!(before, after);
+    }
+}
@@ -1,8 +1,9 @@
 use crate::error::{Error, Result};
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use std::path::Path;
 use std::process::Command;
 
+#[derive(Debug, Clone, Serialize)]
 pub struct VideoInfo {
     pub width: u32,
     pub height: u32,
@@ -0,0 +1,312 @@
+use crate::Segment;
+use crate::analyze::{ContentClass, ContentStats};
+use crate::encode::{Crop, EncodeParams};
+use crate::probe::VideoInfo;
+use std::fmt;
+use std::path::PathBuf;
+
+const BYTES_PER_MB: f64 = 1024.0 * 1024.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FitStop {
+    StartingSettingsFit,
+    WithinTolerance,
+    Converged,
+    AttemptLimit,
+    MinimumExceedsTarget,
+}
+
+#[derive(Debug, Clone)]
+pub enum ProgressEvent {
+    Probed(VideoInfo),
+    CropDetected(Option<Crop>),
+    ContentClassified {
+        class: ContentClass,
+        stats: Option<ContentStats>,
+    },
+    PresetCapped {
+        class: ContentClass,
+        fps: u32,
+        requested_fps: u32,
+    },
+    IdleCompressed {
+        pauses: usize,
+        removed_secs: f64,
+    },
+    BoundariesSnapped(Vec<f64>),
+    SampleEncoded {
+        window: Segment,
+        size: u64,
+    },
+    SegmentStarted(Segment),
+    AttemptStarted {
+        attempt: u32,
+        params: EncodeParams,
+    },
+    QualityMeasured {
+        size: u64,
+        ssim: f64,
+    },
+    SegmentFitted {
+        segment: Segment,
+        size: u64,
+        saved: u64,
+        degraded: bool,
+    },
+    SegmentSplit {
+        segment: Segment,
+        size: u64,
+        halves: (Segment, Segment),
+    },
+    SegmentDegrading {
+        segment: Segment,
+        size: u64,
+    },
+    FitStopped {
+        segment: Segment,
+        attempts: u32,
+        reason: FitStop,
+    },
+    Finished(Vec<PathBuf>),
+}
+
+impl fmt::Display for FitStop {
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let reason = match self {
+            FitStop::StartingSettingsFit => "starting settings fit",
+            FitStop::WithinTolerance => "within tolerance of target",
+            FitStop::Converged => "search converged",
+            FitStop::AttemptLimit => "attempt limit reached",
+            FitStop::MinimumExceedsTarget => "minimum settings exceed target",
+        };
+        formatter.write_str(reason)
+    }
+}
+
+impl fmt::Display for ProgressEvent {
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ProgressEvent::Probed(info) => write!(
+                formatter,
+                "input: {}x{}, {:.1}fps, {:.1}s",
+                info.width, info.height, info.framerate, info.duration_secs
+            ),
+            ProgressEvent::CropDetected(Some(crop)) => write!(formatter, "auto-crop: {crop}"),
+            ProgressEvent::CropDetected(None) => {
+                formatter.write_str("auto-crop: motion covers the whole frame, not cropping")
+            }
+            ProgressEvent::ContentClassified {
+                class,
+                stats: Some(stats),
+            } => write!(
+                formatter,
+                "content: {class} ({} colors, {:.0}% motion, {:.0}% edges)",
+                stats.colors,
+                stats.motion_fraction * 100.0,
+                stats.edge_density * 100.0
+            ),
+            ProgressEvent::ContentClassified { class, stats: None } => {
+                write!(formatter, "content: {class} (set by --content)")
+            }
+            ProgressEvent::PresetCapped {
+                class,
+                fps,
+                requested_fps,
+            } => write!(
+                formatter,
+                "content: {class}, capping fps at {fps} (use --content general to keep {requested_fps})"
+            ),
+            ProgressEvent::IdleCompressed {
+                pauses,
+                removed_secs,
+            } => write!(
+                formatter,
+                "idle compression: removed {removed_secs:.1}s from {pauses} pause(s)"
+            ),
+            ProgressEvent::BoundariesSnapped(cuts) => {
+                let listed: Vec<String> = cuts.iter().map(|cut| format!("{cut:.2}s")).collect();
+                write!(formatter, "scene boundaries: {}", listed.join(", "))
+            }
+            ProgressEvent::SampleEncoded { window, size } => write!(
+                formatter,
+                "sample: {:.1}s - {:.1}s -> {:.2} MB",
+                window.start_secs,
+                window.start_secs + window.duration_secs,
+                *size as f64 / BYTES_PER_MB
+            ),
+            ProgressEvent::SegmentStarted(segment) => write!(
+                formatter,
+                "\nsegment: {:.1}s - {:.1}s ({:.1}s)",
+                segment.start_secs,
+                segment.start_secs + segment.duration_secs,
+                segment.duration_secs,
+            ),
+            ProgressEvent::AttemptStarted { attempt, params } => {
+                write!(
+                    formatter,
+                    "  attempt {}: {}px, {}fps, {} colors",
+                    attempt, params.width, params.fps, params.colors
+                )?;
+                if params.lossy > 0 {
+                    write!(formatter, ", lossy {}", params.lossy)?;
+                }
+                Ok(())
+            }
+            ProgressEvent::QualityMeasured { size, ssim } => write!(
+                formatter,
+                "     {:.2} MB, SSIM {ssim:.4}",
+                *size as f64 / BYTES_PER_MB
+            ),
+            ProgressEvent::SegmentFitted {
+                size,
+                saved,
+                degraded,
+                ..
+            } => {
+                let quality = if *degraded {
+                    "degraded quality"
+                } else {
+                    "fits at full quality"
+                };
+                write!(
+                    formatter,
+                    "  -> {:.2} MB ({quality})",
+                    *size as f64 / BYTES_PER_MB
+                )?;
+                if *saved > 0 {
+                    write!(
+                        formatter,
+                        ", frame optimization saved {:.2} MB",
+                        *saved as f64 / BYTES_PER_MB
+                    )?;
+                }
+                Ok(())
+            }
+            ProgressEvent::SegmentSplit {
+                segment,
+                size,
+                halves,
+            } => write!(
+                formatter,
+                "  -> {:.2} MB (too large, splitting {:.1}s into 2x {:.1}s)",
+                *size as f64 / BYTES_PER_MB,
+                segment.duration_secs,
+                halves.0.duration_secs,
+            ),
+            ProgressEvent::SegmentDegrading { size, .. } => write!(
+                formatter,
+                "  -> {:.2} MB (too large, segment too short to split — degrading quality)",
+                *size as f64 / BYTES_PER_MB,
+            ),
+            ProgressEvent::FitStopped {
+                attempts, reason, ..
+            } => write!(formatter, "  stopped after {attempts} attempt(s): {reason}"),
+            ProgressEvent::Finished(outputs) => {
+                write!(formatter, "\ndone — {} chunk(s) written:", outputs.len())?;
+                for path in outputs {
+                    write!(formatter, "\n  {}", path.display())?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn classification_reports_measured_stats() {
+        let event = ProgressEvent::ContentClassified {
+            class: ContentClass::Terminal,
+            stats: Some(ContentStats {
+                colors: 48,
+                motion_fraction: 0.031,
+                edge_density: 0.12,
+            }),
+        };
+        assert_eq!(
+            event.to_string(),
+            "content: terminal (48 colors, 3% motion, 12% edges)"
+        );
+
+        let event = ProgressEvent::PresetCapped {
+            class: ContentClass::Slides,
+            fps: 5,
+            requested_fps: 15,
+        };
+        assert_eq!(
+            event.to_string(),
+            "content: slides, capping fps at 5 (use --content general to keep 15)"
+        );
+    }
+
+    #[test]
+    fn fitted_segment_renders_size_in_megabytes() {
+        let event = ProgressEvent::SegmentFitted {
+            segment: Segment {
+                start_secs: 0.0,
+                duration_secs: 3.0,
+            },
+            size: 4_980_736,
+            saved: 0,
+            degraded: false,
+        };
+        assert_eq!(event.to_string(), "  -> 4.75 MB (fits at full quality)");
+    }
+
+    #[test]
+    fn fitted_segment_mentions_optimization_savings() {
+        let event = ProgressEvent::SegmentFitted {
+            segment: Segment {
+                start_secs: 0.0,
+                duration_secs: 3.0,
+            },
+            size: 4_980_736,
+            saved: 524_288,
+            degraded: true,
+        };
+        assert_eq!(
+            event.to_string(),
+            "  -> 4.75 MB (degraded quality), frame optimization saved 0.50 MB"
+        );
+    }
+
+    #[test]
+    fn split_renders_both_durations() {
+        let event = ProgressEvent::SegmentSplit {
+            segment: Segment {
+                start_secs: 0.0,
+                duration_secs: 3.0,
+            },
+            size: 12 * 1024 * 1024,
+            halves: (
+                Segment {
+                    start_secs: 0.0,
+                    duration_secs: 1.5,
+                },
+                Segment {
+                    start_secs: 1.5,
+                    duration_secs: 1.5,
+                },
+            ),
+        };
+        assert_eq!(
+            event.to_string(),
+            "  -> 12.00 MB (too large, splitting 3.0s into 2x 1.5s)"
+        );
+    }
+
+    #[test]
+    fn finished_lists_every_output() {
+        let event = ProgressEvent::Finished(vec![
+            PathBuf::from("demo_001.gif"),
+            PathBuf::from("demo_002.gif"),
+        ]);
+        assert_eq!(
+            event.to_string(),
+            "\ndone — 2 chunk(s) written:\n  demo_001.gif\n  demo_002.gif"
+        );
+    }
+}
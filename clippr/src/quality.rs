@@ -0,0 +1,99 @@
+use crate::cancel::{self, CancelToken};
+use crate::encode::{self, EncodeParams};
+use crate::error::{Error, Result};
+use std::path::Path;
+use std::process::Command;
+
+fn comparison_filter(params: &EncodeParams, reference: &EncodeParams) -> String {
+    format!(
+        "[0:v]{source}fps={fps},scale={width}:-2:flags=lanczos,setpts=PTS-STARTPTS[reference];\
+         [1:v]fps={fps},setpts=PTS-STARTPTS[candidate];\
+         [candidate][reference]scale2ref=flags=bicubic[candidate][reference];\
+         [candidate][reference]ssim",
+        source = encode::source_filters(params),
+        fps = reference.fps,
+        width = reference.width,
+    )
+}
+
+fn parse_ssim(output: &str) -> Option<f64> {
+    output
+        .lines()
+        .filter_map(|line| line.split_once(" All:").map(|(_, rest)| rest))
+        .next_back()?
+        .split_whitespace()
+        .next()?
+        .parse()
+        .ok()
+}
+
+pub fn measure_ssim(
+    input: &Path,
+    output: &Path,
+    params: &EncodeParams,
+    reference: &EncodeParams,
+    cancel: &CancelToken,
+) -> Result<f64> {
+    let mut command = Command::new("ffmpeg");
+    command.args(["-hide_banner", "-ss", &format!("{:.3}", params.start_secs)]);
+    command.args(["-t", &format!("{:.3}", params.duration_secs)]);
+    command.args(["-i"]);
+    command.arg(input);
+    command.args(["-i"]);
+    command.arg(output);
+    command.args(["-lavfi", &comparison_filter(params, reference)]);
+    command.args(["-an", "-f", "null", "-"]);
+
+    let result = cancel::run_ffmpeg(&mut command, cancel)?;
+    let stderr = String::from_utf8_lossy(&result.stderr);
+
+    if !result.status.success() {
+        return Err(Error::EncodeFailed(stderr.into_owned()));
+    }
+
+    parse_ssim(&stderr)
+        .ok_or_else(|| Error::EncodeFailed(format!("ssim produced no score:\n{stderr}")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::encode::OutputFormat;
+
+    fn params(width: u32, fps: u32) -> EncodeParams {
+        EncodeParams {
+            format: OutputFormat::Gif,
+            width,
+            fps,
+            colors: 256,
+            crop: "640x360+10+20".parse().ok(),
+            speed: 1.0,
+            start_secs: 3.0,
+            duration_secs: 3.0,
+            skip: Vec::new(),
+            lossy: 0,
+            target_bytes: 0,
+            audio: false,
+        }
+    }
+
+    #[test]
+    fn parse_reads_combined_score() {
+        let output = "frame=   45 fps=0.0 q=-0.0 size=N/A\n\
+                      [Parsed_ssim_7 @ 0x1] SSIM Y:0.981234 (17.26) U:0.990000 (20.00) V:0.991000 (20.46) All:0.984567 (18.11)\n";
+        assert_eq!(parse_ssim(output), Some(0.984567));
+    }
+
+    #[test]
+    fn parse_without_score_is_none() {
+        assert_eq!(parse_ssim("frame=   45 fps=0.0\n"), None);
+    }
+
+    #[test]
+    fn candidate_is_compared_at_reference_size_and_rate() {
+        let filter = comparison_filter(&params(240, 8), &params(480, 15));
+        assert!(filter.starts_with("[0:v]crop=640:360:10:20,fps=15,scale=480:-2:"));
+        assert!(filter.contains("[1:v]fps=15,"));
+        assert!(filter.ends_with("[candidate][reference]ssim"));
+    }
+}
@@ -0,0 +1,143 @@
+use crate::analyze::ContentClass;
+use crate::encode::{Crop, EncodeParams, OutputFormat};
+use crate::error::Result;
+use crate::probe::VideoInfo;
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ChunkReport {
+    pub path: PathBuf,
+    pub start_secs: f64,
+    pub duration_secs: f64,
+    pub width: u32,
+    pub fps: u32,
+    pub colors: u32,
+    pub lossy: u32,
+    pub bytes: u64,
+    pub saved_bytes: u64,
+    pub attempts: u32,
+    pub degraded: bool,
+    pub ssim: Option<f64>,
+}
+
+impl ChunkReport {
+    pub(crate) fn new(
+        path: PathBuf,
+        params: &EncodeParams,
+        bytes: u64,
+        saved_bytes: u64,
+        attempts: u32,
+        degraded: bool,
+    ) -> Self {
+        Self {
+            path,
+            start_secs: params.start_secs,
+            duration_secs: params.duration_secs,
+            width: params.width,
+            fps: params.fps,
+            colors: params.colors,
+            lossy: params.lossy,
+            bytes,
+            saved_bytes,
+            attempts,
+            degraded,
+            ssim: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ConvertReport {
+    pub input: PathBuf,
+    pub source: VideoInfo,
+    pub format: OutputFormat,
+    pub crop: Option<Crop>,
+    pub content: ContentClass,
+    pub speed: f64,
+    pub removed_secs: f64,
+    pub max_bytes: u64,
+    pub saved_bytes: u64,
+    pub outputs: Vec<ChunkReport>,
+}
+
+impl ConvertReport {
+    pub fn paths(&self) -> Vec<PathBuf> {
+        self.outputs
+            .iter()
+            .map(|chunk| chunk.path.clone())
+            .collect()
+    }
+
+    pub fn to_json(&self) -> Result<String> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+}
+
+pub fn manifest_path(stem: &Path) -> PathBuf {
+    let name = format!(
+        "{}.manifest.json",
+        stem.file_name().unwrap_or_default().to_string_lossy()
+    );
+    stem.with_file_name(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn manifest_sits_next_to_outputs() {
+        let result = manifest_path(Path::new("/tmp/output/demo"));
+        assert_eq!(result, PathBuf::from("/tmp/output/demo.manifest.json"));
+    }
+
+    #[test]
+    fn report_serializes_every_chunk_field() {
+        let report = ConvertReport {
+            input: PathBuf::from("demo.mp4"),
+            source: VideoInfo {
+                width: 960,
+                height: 540,
+                duration_secs: 3.0,
+                framerate: 30.0,
+            },
+            format: OutputFormat::WebpLossless,
+            crop: None,
+            content: ContentClass::Terminal,
+            speed: 1.0,
+            removed_secs: 0.0,
+            max_bytes: 1024,
+            saved_bytes: 96,
+            outputs: vec![ChunkReport {
+                path: PathBuf::from("demo.webp"),
+                start_secs: 0.0,
+                duration_secs: 3.0,
+                width: 480,
+                fps: 15,
+                colors: 256,
+                lossy: 40,
+                bytes: 1000,
+                saved_bytes: 96,
+                attempts: 2,
+                degraded: true,
+                ssim: Some(0.97),
+            }],
+        };
+
+        let value: serde_json::Value = serde_json::from_str(&report.to_json().unwrap()).unwrap();
+
+        assert_eq!(value["format"], "webp-lossless");
+        assert_eq!(value["content"], "terminal");
+        assert_eq!(value["source"]["width"], 960);
+        let chunk = &value["outputs"][0];
+        assert_eq!(chunk["path"], "demo.webp");
+        assert_eq!(value["saved_bytes"], 96);
+        assert_eq!(chunk["lossy"], 40);
+        assert_eq!(chunk["bytes"], 1000);
+        assert_eq!(chunk["saved_bytes"], 96);
+        assert_eq!(chunk["attempts"], 2);
+        assert_eq!(chunk["degraded"], true);
+        assert_eq!(chunk["ssim"], 0.97);
+    }
+}
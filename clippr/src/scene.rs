@@ -0,0 +1,215 @@
+use crate::cancel::{self, CancelToken};
+use crate::error::{Error, Result};
+use crate::{MIN_SPLIT_DURATION, Segment};
+use std::path::Path;
+use std::process::Command;
+
+const ANALYSIS_WIDTH: u32 = 160;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum Boundaries {
+    #[default]
+    Fixed,
+    Scene,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MotionSample {
+    pub time_secs: f64,
+    pub score: f64,
+}
+
+fn parse_motion_samples(output: &str, start_secs: f64) -> Vec<MotionSample> {
+    let mut samples = Vec::new();
+    let mut time_secs = None;
+
+    for line in output.lines() {
+        if let Some(position) = line.find("pts_time:") {
+            time_secs = line[position + "pts_time:".len()..]
+                .split_whitespace()
+                .next()
+                .and_then(|value| value.parse::<f64>().ok());
+        } else if let Some(value) = line.trim().strip_prefix("lavfi.scene_score=")
+            && let (Some(time_secs), Ok(score)) = (time_secs, value.parse::<f64>())
+        {
+            samples.push(MotionSample {
+                time_secs: start_secs + time_secs,
+                score,
+            });
+        }
+    }
+
+    samples
+}
+
+pub fn detect_motion(
+    input: &Path,
+    range: &Segment,
+    cancel: &CancelToken,
+) -> Result<Vec<MotionSample>> {
+    let filter = format!("scale={ANALYSIS_WIDTH}:-2,select='gte(scene,0)',metadata=print:file=-");
+
+    let mut command = Command::new("ffmpeg");
+    command.args(["-v", "error", "-ss", &format!("{:.3}", range.start_secs)]);
+    command.args(["-t", &format!("{:.3}", range.duration_secs)]);
+    command.args(["-i"]);
+    command.arg(input);
+    command.args(["-vf", &filter, "-an", "-f", "null", "-"]);
+
+    let output = cancel::run_ffmpeg(&mut command, cancel)?;
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(Error::EncodeFailed(stderr.into_owned()));
+    }
+
+    Ok(parse_motion_samples(
+        &String::from_utf8_lossy(&output.stdout),
+        range.start_secs,
+    ))
+}
+
+pub fn fixed_cuts(range: &Segment, chunk_secs: f64) -> Vec<f64> {
+    let chunk_count = (range.duration_secs / chunk_secs).ceil() as u32;
+    (1..chunk_count)
+        .map(|chunk_index| chunk_index as f64 * chunk_secs)
+        .filter(|&offset| offset < range.duration_secs)
+        .map(|offset| range.start_secs + offset)
+        .collect()
+}
+
+pub fn snap_cuts(
+    cuts: &[f64],
+    samples: &[MotionSample],
+    window_secs: f64,
+    range: &Segment,
+) -> Vec<f64> {
+    let end_secs = range.start_secs + range.duration_secs;
+    let mut snapped: Vec<f64> = Vec::with_capacity(cuts.len());
+
+    for (index, &cut) in cuts.iter().enumerate() {
+        let previous = snapped.last().copied().unwrap_or(range.start_secs);
+        let next = cuts.get(index + 1).copied().unwrap_or(end_secs);
+        let earliest = (cut - window_secs).max(previous + MIN_SPLIT_DURATION);
+        let latest = (cut + window_secs).min(next - MIN_SPLIT_DURATION);
+
+        let quietest = samples
+            .iter()
+            .filter(|sample| sample.time_secs >= earliest && sample.time_secs <= latest)
+            .min_by(|left, right| {
+                left.score.total_cmp(&right.score).then_with(|| {
+                    (left.time_secs - cut)
+                        .abs()
+                        .total_cmp(&(right.time_secs - cut).abs())
+                })
+            });
+
+        snapped.push(match quietest {
+            Some(sample) => sample.time_secs,
+            None => cut,
+        });
+    }
+
+    snapped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample(time_secs: f64, score: f64) -> MotionSample {
+        MotionSample { time_secs, score }
+    }
+
+    fn range(start_secs: f64, duration_secs: f64) -> Segment {
+        Segment {
+            start_secs,
+            duration_secs,
+        }
+    }
+
+    #[test]
+    fn parse_pairs_scores_with_timestamps() {
+        let output = "frame:0    pts:0       pts_time:0\n\
+                      lavfi.scene_score=0.000000\n\
+                      frame:1    pts:512     pts_time:0.0333\n\
+                      lavfi.scene_score=0.125000\n";
+        let samples = parse_motion_samples(output, 0.0);
+        assert_eq!(samples, vec![sample(0.0, 0.0), sample(0.0333, 0.125)]);
+    }
+
+    #[test]
+    fn parse_offsets_timestamps_by_the_trim_start() {
+        let output = "frame:0    pts:0       pts_time:0.5
+                      lavfi.scene_score=0.250000
+";
+        let samples = parse_motion_samples(output, 4.0);
+        assert_eq!(samples, vec![sample(4.5, 0.25)]);
+    }
+
+    #[test]
+    fn parse_ignores_unrelated_lines() {
+        assert!(parse_motion_samples("lavfi.scene_score=0.5\nnoise\n", 0.0).is_empty());
+    }
+
+    #[test]
+    fn fixed_cuts_fall_on_chunk_multiples() {
+        assert_eq!(fixed_cuts(&range(0.0, 10.9), 3.0), vec![3.0, 6.0, 9.0]);
+        assert_eq!(fixed_cuts(&range(0.0, 9.0), 3.0), vec![3.0, 6.0]);
+        assert!(fixed_cuts(&range(0.0, 2.0), 3.0).is_empty());
+    }
+
+    #[test]
+    fn fixed_cuts_start_at_range_start() {
+        assert_eq!(fixed_cuts(&range(1.5, 7.0), 3.0), vec![4.5, 7.5]);
+    }
+
+    #[test]
+    fn snap_stays_inside_range() {
+        let samples = [sample(1.2, 0.0), sample(2.8, 0.2)];
+        assert_eq!(
+            snap_cuts(&[3.0], &samples, 2.0, &range(1.0, 5.0)),
+            vec![2.8]
+        );
+    }
+
+    #[test]
+    fn snap_moves_cut_to_quietest_frame_in_window() {
+        let samples = [
+            sample(2.5, 0.30),
+            sample(2.8, 0.01),
+            sample(3.0, 0.40),
+            sample(3.4, 0.05),
+        ];
+        assert_eq!(
+            snap_cuts(&[3.0], &samples, 1.0, &range(0.0, 10.0)),
+            vec![2.8]
+        );
+    }
+
+    #[test]
+    fn snap_breaks_ties_toward_nominal_cut() {
+        let samples = [sample(2.2, 0.0), sample(3.1, 0.0), sample(3.9, 0.0)];
+        assert_eq!(
+            snap_cuts(&[3.0], &samples, 1.0, &range(0.0, 10.0)),
+            vec![3.1]
+        );
+    }
+
+    #[test]
+    fn snap_keeps_cut_without_samples_in_window() {
+        let samples = [sample(0.5, 0.0), sample(8.0, 0.0)];
+        assert_eq!(
+            snap_cuts(&[3.0, 6.0], &samples, 1.0, &range(0.0, 10.0)),
+            vec![3.0, 6.0]
+        );
+    }
+
+    #[test]
+    fn snap_never_produces_tiny_segments() {
+        let samples = [sample(3.9, 0.0), sample(4.2, 0.0)];
+        let cuts = snap_cuts(&[3.0, 4.5], &samples, 1.0, &range(0.0, 10.0));
+        assert_eq!(cuts, vec![3.9, 4.5]);
+        assert!(cuts[1] - cuts[0] >= MIN_SPLIT_DURATION);
+    }
+}
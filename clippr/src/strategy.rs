@@ -1,17 +1,95 @@
-use crate::encode::{self, EncodeParams};
+use crate::Segment;
+use crate::cancel::CancelToken;
+use crate::encode::{Crop, EncodeParams, Encoder, OutputFormat, TRUE_COLOR};
 use crate::error::{Error, Result};
-use std::path::Path;
+use crate::idle;
+use crate::progress::{FitStop, ProgressEvent};
+use std::path::{Path, PathBuf};
 
-const MAX_ATTEMPTS: u32 = 5;
+const MAX_ATTEMPTS: u32 = 8;
 const MIN_WIDTH: u32 = 240;
 const MIN_FPS: u32 = 8;
 const COLOR_STEPS: &[u32] = &[256, 128, 64, 32];
+const APNG_COLOR_STEPS: &[u32] = &[TRUE_COLOR, 256, 128, 64, 32];
+const NO_COLOR_STEPS: &[u32] = &[TRUE_COLOR];
+const LOSSY_STEPS: &[u32] = &[0, 40, 80];
+const NO_LOSSY_STEPS: &[u32] = &[0];
 const SAFETY_MARGIN: f64 = 0.90;
+const MAX_BISECT_ATTEMPTS: u32 = 8;
+const MAX_QUALITY_ATTEMPTS: u32 = 16;
+const BISECT_CONVERGENCE: f64 = 0.02;
+const LOSSY_SCALE: f64 = 40.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Axis {
+    Width,
+    Fps,
+    Colors,
+    Lossy,
+}
+
+pub const DEFAULT_ORDER: [Axis; 4] = [Axis::Width, Axis::Fps, Axis::Colors, Axis::Lossy];
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum FitMode {
+    #[default]
+    Ladder,
+    Bisect,
+    Quality,
+}
+
+pub struct FitTarget {
+    pub bytes: u64,
+    pub mode: FitMode,
+    pub tolerance: f64,
+}
 
 pub struct InitialParams {
     pub width: u32,
     pub fps: u32,
     pub colors: u32,
+    pub crop: Option<Crop>,
+    pub speed: f64,
+    pub gaps: Vec<Segment>,
+    pub format: OutputFormat,
+    pub audio: bool,
+    pub order: [Axis; 4],
+}
+
+impl InitialParams {
+    pub fn encode_params(&self, segment: &Segment, target_bytes: u64) -> EncodeParams {
+        EncodeParams {
+            format: self.format,
+            width: self.width,
+            fps: self.fps,
+            colors: self.colors,
+            crop: self.crop,
+            speed: self.speed,
+            start_secs: segment.start_secs,
+            duration_secs: segment.duration_secs,
+            skip: idle::gaps_within(segment, &self.gaps),
+            lossy: 0,
+            target_bytes,
+            audio: self.audio,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Fitted {
+    pub bytes: u64,
+    pub saved_bytes: u64,
+    pub params: EncodeParams,
+    pub attempts: u32,
+    pub ssim: Option<f64>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Prediction {
+    pub params: EncodeParams,
+    pub bytes: u64,
+    pub reductions: u32,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -19,97 +97,633 @@ struct EncodeSettings {
     width: u32,
     fps: u32,
     color_index: usize,
+    lossy_index: usize,
 }
 
-fn resolve_color_index(requested_colors: u32) -> usize {
-    COLOR_STEPS
+fn color_steps(format: OutputFormat) -> &'static [u32] {
+    match format {
+        OutputFormat::Apng => APNG_COLOR_STEPS,
+        OutputFormat::Mp4 => NO_COLOR_STEPS,
+        _ => COLOR_STEPS,
+    }
+}
+
+fn lossy_steps(format: OutputFormat) -> &'static [u32] {
+    match format {
+        OutputFormat::Gif => LOSSY_STEPS,
+        _ => NO_LOSSY_STEPS,
+    }
+}
+
+fn active_order(order: &[Axis], color_steps: &[u32], lossy_steps: &[u32]) -> Vec<Axis> {
+    order
+        .iter()
+        .copied()
+        .filter(|&axis| match axis {
+            Axis::Colors => color_steps.len() > 1,
+            Axis::Lossy => lossy_steps.len() > 1,
+            _ => true,
+        })
+        .collect()
+}
+
+fn resolve_color_index(color_steps: &[u32], requested_colors: u32) -> usize {
+    color_steps
         .iter()
         .position(|&color| color <= requested_colors)
         .unwrap_or(0)
 }
 
-fn reduce_params(settings: &EncodeSettings, ratio: f64) -> Option<EncodeSettings> {
-    let new_width = ((settings.width as f64) * ratio.sqrt()).max(MIN_WIDTH as f64) as u32;
-    if new_width < settings.width {
-        return Some(EncodeSettings {
-            width: new_width,
-            fps: settings.fps,
-            color_index: settings.color_index,
-        });
+fn start_settings(initial: &InitialParams, color_steps: &[u32]) -> EncodeSettings {
+    EncodeSettings {
+        width: initial.width,
+        fps: initial.fps,
+        color_index: resolve_color_index(color_steps, initial.colors),
+        lossy_index: 0,
     }
+}
 
-    let new_fps = ((settings.fps as f64) * ratio).max(MIN_FPS as f64) as u32;
-    if new_fps < settings.fps {
-        return Some(EncodeSettings {
-            width: settings.width,
-            fps: new_fps,
-            color_index: settings.color_index,
-        });
+fn single_axis_reductions(
+    settings: &EncodeSettings,
+    ratio: f64,
+    color_steps: &[u32],
+    lossy_steps: &[u32],
+    order: &[Axis],
+) -> Vec<EncodeSettings> {
+    order
+        .iter()
+        .filter_map(|axis| {
+            let mut reduced = settings.clone();
+            match axis {
+                Axis::Width => {
+                    reduced.width =
+                        ((settings.width as f64) * ratio.sqrt()).max(MIN_WIDTH as f64) as u32;
+                }
+                Axis::Fps => {
+                    reduced.fps = ((settings.fps as f64) * ratio).max(MIN_FPS as f64) as u32;
+                }
+                Axis::Colors => {
+                    reduced.color_index = (settings.color_index + 1).min(color_steps.len() - 1);
+                }
+                Axis::Lossy => {
+                    reduced.lossy_index = (settings.lossy_index + 1).min(lossy_steps.len() - 1);
+                }
+            }
+            let smaller = reduced.width < settings.width
+                || reduced.fps < settings.fps
+                || reduced.color_index > settings.color_index
+                || reduced.lossy_index > settings.lossy_index;
+            smaller.then_some(reduced)
+        })
+        .collect()
+}
+
+fn reduce_params(
+    settings: &EncodeSettings,
+    ratio: f64,
+    color_steps: &[u32],
+    lossy_steps: &[u32],
+    order: &[Axis],
+) -> Option<EncodeSettings> {
+    single_axis_reductions(settings, ratio, color_steps, lossy_steps, order)
+        .into_iter()
+        .next()
+}
+
+fn pick_candidate(
+    base_bytes: u64,
+    base_ssim: f64,
+    target_bytes: u64,
+    measured: &[(u64, f64)],
+) -> Option<usize> {
+    let by_ssim = |first: &(usize, &(u64, f64)), second: &(usize, &(u64, f64))| {
+        first.1.1.total_cmp(&second.1.1)
+    };
+
+    let fitting = measured
+        .iter()
+        .enumerate()
+        .filter(|(_, (bytes, _))| *bytes <= target_bytes)
+        .max_by(by_ssim);
+    if let Some((index, _)) = fitting {
+        return Some(index);
     }
 
-    if settings.color_index + 1 < COLOR_STEPS.len() {
-        return Some(EncodeSettings {
+    let loss_per_byte = |&(bytes, ssim): &(u64, f64)| {
+        (base_ssim - ssim).max(0.0) / base_bytes.saturating_sub(bytes) as f64
+    };
+    measured
+        .iter()
+        .enumerate()
+        .filter(|(_, (bytes, _))| *bytes < base_bytes)
+        .min_by(|first, second| loss_per_byte(first.1).total_cmp(&loss_per_byte(second.1)))
+        .map(|(index, _)| index)
+}
+
+struct Fitter<'a, E> {
+    encoder: &'a E,
+    input: &'a Path,
+    initial: &'a InitialParams,
+    segment: &'a Segment,
+    target_bytes: u64,
+    color_steps: &'static [u32],
+    lossy_steps: &'static [u32],
+    order: Vec<Axis>,
+    cancel: &'a CancelToken,
+}
+
+impl<E: Encoder> Fitter<'_, E> {
+    fn encode(
+        &self,
+        output: &Path,
+        settings: &EncodeSettings,
+        attempt: u32,
+        on_progress: &mut impl FnMut(&ProgressEvent),
+    ) -> Result<Fitted> {
+        let params = EncodeParams {
             width: settings.width,
             fps: settings.fps,
-            color_index: settings.color_index + 1,
+            colors: self.color_steps[settings.color_index],
+            lossy: self.lossy_steps[settings.lossy_index],
+            ..self.initial.encode_params(self.segment, self.target_bytes)
+        };
+
+        on_progress(&ProgressEvent::AttemptStarted {
+            attempt,
+            params: params.clone(),
         });
+
+        let bytes = self
+            .encoder
+            .encode(self.input, output, &params, self.cancel)?;
+        let saved_bytes = self.encoder.optimize(output, &params, self.cancel)?;
+        Ok(Fitted {
+            bytes: bytes.saturating_sub(saved_bytes),
+            saved_bytes,
+            params,
+            attempts: attempt,
+            ssim: None,
+        })
+    }
+
+    fn ladder(
+        &self,
+        output: &Path,
+        start: EncodeSettings,
+        on_progress: &mut impl FnMut(&ProgressEvent),
+    ) -> Result<Fitted> {
+        let mut settings = start;
+
+        for attempt in 0..MAX_ATTEMPTS {
+            let fitted = self.encode(output, &settings, attempt + 1, on_progress)?;
+
+            if fitted.bytes <= self.target_bytes {
+                return Ok(fitted);
+            }
+
+            let ratio = (self.target_bytes as f64 / fitted.bytes as f64) * SAFETY_MARGIN;
+
+            settings = match reduce_params(
+                &settings,
+                ratio,
+                self.color_steps,
+                self.lossy_steps,
+                &self.order,
+            ) {
+                Some(reduced) => reduced,
+                None => return Err(Error::TargetUnreachable(attempt + 1)),
+            };
+        }
+
+        Err(Error::TargetUnreachable(MAX_ATTEMPTS))
     }
 
-    None
+    fn quality(
+        &self,
+        output: &Path,
+        start: EncodeSettings,
+        on_progress: &mut impl FnMut(&ProgressEvent),
+    ) -> Result<Fitted> {
+        let reference = self.initial.encode_params(self.segment, self.target_bytes);
+        let mut settings = start;
+        let mut current = self.encode(output, &settings, 1, on_progress)?;
+        let mut attempt = 1;
+
+        while current.bytes > self.target_bytes {
+            let ratio = (self.target_bytes as f64 / current.bytes as f64) * SAFETY_MARGIN;
+            let candidates = single_axis_reductions(
+                &settings,
+                ratio,
+                self.color_steps,
+                self.lossy_steps,
+                &self.order,
+            );
+            if candidates.is_empty() || attempt + candidates.len() as u32 > MAX_QUALITY_ATTEMPTS {
+                return Err(Error::TargetUnreachable(attempt));
+            }
+
+            let mut measured: Vec<(PathBuf, EncodeSettings, Fitted)> = Vec::new();
+            for (index, candidate) in candidates.into_iter().enumerate() {
+                attempt += 1;
+                let path = output.with_extension(format!(
+                    "candidate{index}.{}",
+                    output.extension().unwrap_or_default().to_string_lossy()
+                ));
+                let result = self
+                    .encode(&path, &candidate, attempt, on_progress)
+                    .and_then(|fitted| {
+                        let ssim = self.encoder.measure_quality(
+                            self.input,
+                            &path,
+                            &fitted.params,
+                            &reference,
+                            self.cancel,
+                        )?;
+                        Ok(Fitted {
+                            ssim: Some(ssim),
+                            ..fitted
+                        })
+                    });
+                match result {
+                    Ok(fitted) => {
+                        on_progress(&ProgressEvent::QualityMeasured {
+                            size: fitted.bytes,
+                            ssim: fitted.ssim.unwrap_or_default(),
+                        });
+                        measured.push((path, candidate, fitted));
+                    }
+                    Err(error) => {
+                        let _ = std::fs::remove_file(&path);
+                        for (path, ..) in &measured {
+                            let _ = std::fs::remove_file(path);
+                        }
+                        return Err(error);
+                    }
+                }
+            }
+
+            let scores: Vec<(u64, f64)> = measured
+                .iter()
+                .map(|(_, _, fitted)| (fitted.bytes, fitted.ssim.unwrap_or_default()))
+                .collect();
+            let base_ssim = current.ssim.unwrap_or(1.0);
+            let chosen = pick_candidate(current.bytes, base_ssim, self.target_bytes, &scores);
+
+            let Some(chosen) = chosen else {
+                for (path, ..) in &measured {
+                    let _ = std::fs::remove_file(path);
+                }
+                return Err(Error::TargetUnreachable(attempt));
+            };
+            for (index, (path, candidate, fitted)) in measured.into_iter().enumerate() {
+                if index == chosen {
+                    std::fs::rename(&path, output)?;
+                    settings = candidate;
+                    current = fitted;
+                } else {
+                    let _ = std::fs::remove_file(&path);
+                }
+            }
+        }
+
+        Ok(Fitted {
+            attempts: attempt,
+            ..current
+        })
+    }
+
+    fn predict_position(
+        &self,
+        start: &EncodeSettings,
+        floor: &EncodeSettings,
+        budget: f64,
+        low: f64,
+        high: f64,
+    ) -> f64 {
+        let fits = |position: f64| {
+            model_size(
+                &settings_at(start, floor, position, &self.order),
+                self.color_steps,
+                self.lossy_steps,
+            ) <= budget
+        };
+
+        if !fits(low) {
+            return low;
+        }
+
+        let mut lower = low;
+        let mut upper = high;
+        for _ in 0..32 {
+            let middle = (lower + upper) / 2.0;
+            if fits(middle) {
+                lower = middle;
+            } else {
+                upper = middle;
+            }
+        }
+        lower
+    }
+
+    fn bisect(
+        &self,
+        output: &Path,
+        start: EncodeSettings,
+        tolerance: f64,
+        on_progress: &mut impl FnMut(&ProgressEvent),
+    ) -> Result<Fitted> {
+        let floor = floor_settings(&start, self.color_steps, self.lossy_steps);
+        let scratch = output.with_extension(format!(
+            "attempt.{}",
+            output.extension().unwrap_or_default().to_string_lossy()
+        ));
+        let fill_bytes = self.target_bytes as f64 * (1.0 - tolerance / 2.0);
+
+        let mut low = 0.0;
+        let mut high = 1.0;
+        let mut best: Option<Fitted> = None;
+        let mut guess = 1.0;
+        let mut attempt = 0;
+
+        let reason = loop {
+            if attempt == MAX_BISECT_ATTEMPTS {
+                break FitStop::AttemptLimit;
+            }
+
+            let settings = settings_at(&start, &floor, guess, &self.order);
+            attempt += 1;
+            let fitted = match self.encode(&scratch, &settings, attempt, on_progress) {
+                Ok(fitted) => fitted,
+                Err(error) => {
+                    let _ = std::fs::remove_file(&scratch);
+                    return Err(error);
+                }
+            };
+            let size = fitted.bytes;
+
+            if size <= self.target_bytes {
+                std::fs::rename(&scratch, output)?;
+                best = Some(fitted);
+                low = guess;
+                if guess >= 1.0 {
+                    break FitStop::StartingSettingsFit;
+                }
+                if size as f64 >= self.target_bytes as f64 * (1.0 - tolerance) {
+                    break FitStop::WithinTolerance;
+                }
+            } else {
+                high = guess;
+                if guess <= 0.0 {
+                    break FitStop::MinimumExceedsTarget;
+                }
+            }
+
+            if best.is_some() && high - low < BISECT_CONVERGENCE {
+                break FitStop::Converged;
+            }
+
+            let bytes_per_unit =
+                size as f64 / model_size(&settings, self.color_steps, self.lossy_steps);
+            let predicted =
+                self.predict_position(&start, &floor, fill_bytes / bytes_per_unit, low, high);
+            let low_settings = settings_at(&start, &floor, low, &self.order);
+            let high_settings = settings_at(&start, &floor, high, &self.order);
+            let untried = |position: f64| {
+                let settings = settings_at(&start, &floor, position, &self.order);
+                settings != high_settings && (best.is_none() || settings != low_settings)
+            };
+
+            let next = [predicted, (low + high) / 2.0, 0.0]
+                .into_iter()
+                .filter(|&position| position >= low && position < high)
+                .find(|&position| untried(position) && (best.is_none() || position > low));
+
+            guess = match next {
+                Some(position) => position,
+                None if best.is_some() => break FitStop::Converged,
+                None => break FitStop::MinimumExceedsTarget,
+            };
+        };
+
+        let _ = std::fs::remove_file(&scratch);
+        on_progress(&ProgressEvent::FitStopped {
+            segment: *self.segment,
+            attempts: attempt,
+            reason,
+        });
+
+        best.map(|fitted| Fitted {
+            attempts: attempt,
+            ..fitted
+        })
+        .ok_or(Error::TargetUnreachable(attempt))
+    }
+}
+
+fn floor_settings(
+    start: &EncodeSettings,
+    color_steps: &[u32],
+    lossy_steps: &[u32],
+) -> EncodeSettings {
+    EncodeSettings {
+        width: MIN_WIDTH.min(start.width),
+        fps: MIN_FPS.min(start.fps),
+        color_index: (color_steps.len() - 1).max(start.color_index),
+        lossy_index: (lossy_steps.len() - 1).max(start.lossy_index),
+    }
+}
+
+fn settings_at(
+    start: &EncodeSettings,
+    floor: &EncodeSettings,
+    position: f64,
+    order: &[Axis],
+) -> EncodeSettings {
+    let interpolate =
+        |low: u32, high: u32, amount: f64| low + ((high - low) as f64 * amount).round() as u32;
+    let step_back = |floor_index: usize, start_index: usize, amount: f64| {
+        let range = (floor_index - start_index) as f64;
+        floor_index - (range * amount).round() as usize
+    };
+    let stage_position = position.clamp(0.0, 1.0) * order.len() as f64;
+
+    let mut settings = start.clone();
+    for (stage, axis) in order.iter().enumerate() {
+        let amount = (stage_position - (order.len() - stage - 1) as f64).clamp(0.0, 1.0);
+        match axis {
+            Axis::Width => settings.width = interpolate(floor.width, start.width, amount),
+            Axis::Fps => settings.fps = interpolate(floor.fps, start.fps, amount),
+            Axis::Colors => {
+                settings.color_index = step_back(floor.color_index, start.color_index, amount)
+            }
+            Axis::Lossy => {
+                settings.lossy_index = step_back(floor.lossy_index, start.lossy_index, amount)
+            }
+        }
+    }
+    settings
+}
+
+fn model_size(settings: &EncodeSettings, color_steps: &[u32], lossy_steps: &[u32]) -> f64 {
+    let color_bits = (color_steps[settings.color_index].max(2) as f64).log2();
+    let lossy_factor = 1.0 + lossy_steps[settings.lossy_index] as f64 / LOSSY_SCALE;
+    (settings.width as f64).powi(2) * settings.fps as f64 * color_bits / lossy_factor
 }
 
+#[allow(clippy::too_many_arguments)]
 pub fn auto_encode(
+    encoder: &impl Encoder,
     input: &Path,
     output: &Path,
-    target_bytes: u64,
+    target: &FitTarget,
     initial: &InitialParams,
-    start_secs: f64,
-    duration_secs: f64,
-    on_progress: &mut impl FnMut(&str),
-) -> Result<u64> {
-    let mut settings = EncodeSettings {
-        width: initial.width,
-        fps: initial.fps,
-        color_index: resolve_color_index(initial.colors),
+    segment: &Segment,
+    cancel: &CancelToken,
+    on_progress: &mut impl FnMut(&ProgressEvent),
+) -> Result<Fitted> {
+    let color_steps = color_steps(initial.format);
+    let lossy_steps = lossy_steps(initial.format);
+    let start = start_settings(initial, color_steps);
+    let fitter = Fitter {
+        encoder,
+        input,
+        initial,
+        segment,
+        target_bytes: target.bytes,
+        color_steps,
+        lossy_steps,
+        order: active_order(&initial.order, color_steps, lossy_steps),
+        cancel,
     };
 
-    for attempt in 0..MAX_ATTEMPTS {
-        let params = EncodeParams {
+    match target.mode {
+        FitMode::Ladder => fitter.ladder(output, start, on_progress),
+        FitMode::Bisect => fitter.bisect(output, start, target.tolerance, on_progress),
+        FitMode::Quality => fitter.quality(output, start, on_progress),
+    }
+}
+
+pub fn predict_ladder(
+    initial: &InitialParams,
+    segment: &Segment,
+    start_bytes: u64,
+    target_bytes: u64,
+) -> Prediction {
+    let color_steps = color_steps(initial.format);
+    let lossy_steps = lossy_steps(initial.format);
+    let order = active_order(&initial.order, color_steps, lossy_steps);
+    let start = start_settings(initial, color_steps);
+    let start_size = model_size(&start, color_steps, lossy_steps);
+
+    let mut settings = start;
+    let mut bytes = start_bytes;
+    let mut reductions = 0;
+    while bytes > target_bytes && reductions + 1 < MAX_ATTEMPTS {
+        let ratio = (target_bytes as f64 / bytes as f64) * SAFETY_MARGIN;
+        match reduce_params(&settings, ratio, color_steps, lossy_steps, &order) {
+            Some(reduced) => settings = reduced,
+            None => break,
+        }
+        let scale = model_size(&settings, color_steps, lossy_steps) / start_size;
+        bytes = (start_bytes as f64 * scale) as u64;
+        reductions += 1;
+    }
+
+    Prediction {
+        params: EncodeParams {
             width: settings.width,
             fps: settings.fps,
-            colors: COLOR_STEPS[settings.color_index],
-            start_secs,
-            duration_secs,
-        };
-
-        on_progress(&format!(
-            "  attempt {}: {}px, {}fps, {} colors",
-            attempt + 1,
-            settings.width,
-            settings.fps,
-            COLOR_STEPS[settings.color_index]
-        ));
+            colors: color_steps[settings.color_index],
+            lossy: lossy_steps[settings.lossy_index],
+            ..initial.encode_params(segment, target_bytes)
+        },
+        bytes,
+        reductions,
+    }
+}
 
-        let size = encode::encode(input, output, &params)?;
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::encode::FakeEncoder;
+    use std::sync::atomic::{AtomicUsize, Ordering};
 
-        if size <= target_bytes {
-            return Ok(size);
+    fn gif_initial() -> InitialParams {
+        InitialParams {
+            width: 480,
+            fps: 15,
+            colors: 256,
+            crop: None,
+            speed: 1.0,
+            gaps: Vec::new(),
+            format: OutputFormat::Gif,
+            audio: false,
+            order: DEFAULT_ORDER,
         }
+    }
 
-        let ratio = (target_bytes as f64 / size as f64) * SAFETY_MARGIN;
+    fn run_auto_encode(encoder: &FakeEncoder, target_bytes: u64) -> Result<u64> {
+        run_fit(encoder, target_bytes, FitMode::Ladder)
+    }
+
+    fn run_fit(encoder: &FakeEncoder, target_bytes: u64, mode: FitMode) -> Result<u64> {
+        fit(encoder, target_bytes, mode).map(|fitted| fitted.bytes)
+    }
 
-        settings = match reduce_params(&settings, ratio) {
-            Some(reduced) => reduced,
-            None => return Err(Error::TargetUnreachable(attempt + 1)),
+    fn fit(encoder: &FakeEncoder, target_bytes: u64, mode: FitMode) -> Result<Fitted> {
+        static NEXT_OUTPUT: AtomicUsize = AtomicUsize::new(0);
+        let output = std::env::temp_dir().join(format!(
+            "clippr-strategy-{}-{}.gif",
+            std::process::id(),
+            NEXT_OUTPUT.fetch_add(1, Ordering::Relaxed)
+        ));
+        let target = FitTarget {
+            bytes: target_bytes,
+            mode,
+            tolerance: 0.1,
         };
+        let segment = Segment {
+            start_secs: 0.0,
+            duration_secs: 1.0,
+        };
+        let result = auto_encode(
+            encoder,
+            Path::new("input.mp4"),
+            &output,
+            &target,
+            &gif_initial(),
+            &segment,
+            &CancelToken::new(),
+            &mut |_| {},
+        );
+        let _ = std::fs::remove_file(&output);
+        result
     }
 
-    Err(Error::TargetUnreachable(MAX_ATTEMPTS))
-}
+    #[test]
+    fn auto_encode_stops_at_first_fit() {
+        let encoder = FakeEncoder::new(1.0, 1000.0);
+        let size = run_auto_encode(&encoder, 2000).unwrap();
+        assert_eq!(size, 1000);
+        assert_eq!(encoder.calls().len(), 1);
+    }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+    #[test]
+    fn auto_encode_shrinks_width_before_fps() {
+        let encoder = FakeEncoder::new(1.0, 2000.0);
+        let size = run_auto_encode(&encoder, 1000).unwrap();
+        let calls = encoder.calls();
+        assert!(size <= 1000);
+        assert!(calls[1].width < calls[0].width);
+        assert_eq!(calls[1].fps, 15);
+    }
+
+    #[test]
+    fn auto_encode_gives_up_when_ladder_exhausted() {
+        let encoder = FakeEncoder::new(1.0, 1_000_000.0);
+        let result = run_auto_encode(&encoder, 10);
+        assert!(matches!(result, Err(Error::TargetUnreachable(_))));
+    }
 
     #[test]
     fn reduce_prefers_width_first() {
@@ -117,8 +731,10 @@ mod tests {
             width: 480,
             fps: 15,
             color_index: 0,
+            lossy_index: 0,
         };
-        let result = reduce_params(&settings, 0.5).unwrap();
+        let result =
+            reduce_params(&settings, 0.5, COLOR_STEPS, LOSSY_STEPS, &DEFAULT_ORDER).unwrap();
         assert!(result.width < 480);
         assert_eq!(result.fps, 15);
         assert_eq!(result.color_index, 0);
@@ -130,8 +746,10 @@ mod tests {
             width: MIN_WIDTH,
             fps: 15,
             color_index: 0,
+            lossy_index: 0,
         };
-        let result = reduce_params(&settings, 0.5).unwrap();
+        let result =
+            reduce_params(&settings, 0.5, COLOR_STEPS, LOSSY_STEPS, &DEFAULT_ORDER).unwrap();
         assert_eq!(result.width, MIN_WIDTH);
         assert!(result.fps < 15);
         assert_eq!(result.color_index, 0);
@@ -143,8 +761,10 @@ mod tests {
             width: MIN_WIDTH,
             fps: MIN_FPS,
             color_index: 0,
+            lossy_index: 0,
         };
-        let result = reduce_params(&settings, 0.5).unwrap();
+        let result =
+            reduce_params(&settings, 0.5, COLOR_STEPS, LOSSY_STEPS, &DEFAULT_ORDER).unwrap();
         assert_eq!(result.width, MIN_WIDTH);
         assert_eq!(result.fps, MIN_FPS);
         assert_eq!(result.color_index, 1);
@@ -156,8 +776,104 @@ mod tests {
             width: MIN_WIDTH,
             fps: MIN_FPS,
             color_index: COLOR_STEPS.len() - 1,
+            lossy_index: LOSSY_STEPS.len() - 1,
         };
-        assert!(reduce_params(&settings, 0.5).is_none());
+        assert!(reduce_params(&settings, 0.5, COLOR_STEPS, LOSSY_STEPS, &DEFAULT_ORDER).is_none());
+    }
+
+    #[test]
+    fn reduce_turns_lossy_after_colors_run_out() {
+        let settings = EncodeSettings {
+            width: MIN_WIDTH,
+            fps: MIN_FPS,
+            color_index: COLOR_STEPS.len() - 1,
+            lossy_index: 0,
+        };
+        let result =
+            reduce_params(&settings, 0.5, COLOR_STEPS, LOSSY_STEPS, &DEFAULT_ORDER).unwrap();
+        assert_eq!(result.color_index, settings.color_index);
+        assert_eq!(LOSSY_STEPS[result.lossy_index], 40);
+    }
+
+    #[test]
+    fn reductions_offer_one_change_per_axis() {
+        let settings = EncodeSettings {
+            width: 480,
+            fps: 15,
+            color_index: 0,
+            lossy_index: 0,
+        };
+        let reductions =
+            single_axis_reductions(&settings, 0.5, COLOR_STEPS, LOSSY_STEPS, &DEFAULT_ORDER);
+        assert_eq!(reductions.len(), 4);
+        assert_eq!(reductions[0].width, 339);
+        assert_eq!(reductions[1].fps, 8);
+        assert_eq!(reductions[2].color_index, 1);
+        assert_eq!(reductions[3].lossy_index, 1);
+    }
+
+    #[test]
+    fn pick_prefers_best_quality_among_fitting_candidates() {
+        let measured = [(900, 0.90), (950, 0.97), (1200, 0.99)];
+        assert_eq!(pick_candidate(2000, 1.0, 1000, &measured), Some(1));
+    }
+
+    #[test]
+    fn pick_trades_least_quality_per_byte_when_nothing_fits() {
+        let measured = [(1500, 0.90), (1800, 0.99), (2100, 0.999)];
+        assert_eq!(pick_candidate(2000, 1.0, 1000, &measured), Some(1));
+        assert_eq!(pick_candidate(2000, 1.0, 1000, &[(2000, 1.0)]), None);
+    }
+
+    #[test]
+    fn quality_fit_keeps_width_for_text_heavy_content() {
+        let mut encoder = FakeEncoder::new(1.0, 10_000.0);
+        encoder.quality_of = |params| {
+            let width_loss = 1.0 - params.width as f64 / 480.0;
+            let fps_loss = 1.0 - params.fps as f64 / 15.0;
+            let color_loss = 1.0 - (params.colors as f64).log2() / 8.0;
+            1.0 - 0.8 * width_loss
+                - 0.1 * fps_loss
+                - 0.01 * color_loss
+                - params.lossy as f64 / 1000.0
+        };
+
+        let fitted = fit(&encoder, 9000, FitMode::Quality).unwrap();
+
+        assert!(fitted.bytes <= 9000);
+        assert_eq!((fitted.params.width, fitted.params.colors), (480, 128));
+        assert_eq!(fitted.attempts, 5);
+        assert!(fitted.ssim.is_some());
+
+        let ladder = fit(&FakeEncoder::new(1.0, 10_000.0), 9000, FitMode::Ladder).unwrap();
+        assert!(ladder.params.width < 480);
+    }
+
+    #[test]
+    fn quality_fit_walks_cheapest_reductions_until_it_fits() {
+        let encoder = FakeEncoder::new(1.0, 10_000.0);
+        let fitted = fit(&encoder, 3000, FitMode::Quality).unwrap();
+        assert!(fitted.bytes <= 3000);
+        assert!(fitted.attempts <= MAX_QUALITY_ATTEMPTS);
+    }
+
+    #[test]
+    fn lossy_steps_follow_format() {
+        assert_eq!(lossy_steps(OutputFormat::Gif), LOSSY_STEPS);
+        assert_eq!(lossy_steps(OutputFormat::Apng), NO_LOSSY_STEPS);
+    }
+
+    #[test]
+    fn auto_encode_rescues_busy_segment_with_lossy() {
+        let encoder = FakeEncoder::new(1.0, 100_000.0);
+        let size = run_auto_encode(&encoder, 5000).unwrap();
+        let last = encoder.calls().last().cloned().unwrap();
+        assert!(size <= 5000);
+        assert_eq!(
+            (last.width, last.fps, last.colors),
+            (MIN_WIDTH, MIN_FPS, 32)
+        );
+        assert_eq!(last.lossy, 40);
     }
 
     #[test]
@@ -166,8 +882,10 @@ mod tests {
             width: 260,
             fps: 15,
             color_index: 0,
+            lossy_index: 0,
         };
-        let result = reduce_params(&settings, 0.01).unwrap();
+        let result =
+            reduce_params(&settings, 0.01, COLOR_STEPS, LOSSY_STEPS, &DEFAULT_ORDER).unwrap();
         assert_eq!(result.width, MIN_WIDTH);
     }
 
@@ -177,8 +895,10 @@ mod tests {
             width: MIN_WIDTH,
             fps: 15,
             color_index: 0,
+            lossy_index: 0,
         };
-        let result = reduce_params(&settings, 0.01).unwrap();
+        let result =
+            reduce_params(&settings, 0.01, COLOR_STEPS, LOSSY_STEPS, &DEFAULT_ORDER).unwrap();
         assert_eq!(result.fps, MIN_FPS);
     }
 
@@ -188,9 +908,11 @@ mod tests {
             width: 480,
             fps: 15,
             color_index: 0,
+            lossy_index: 0,
         };
         let ratio = 0.5;
-        let result = reduce_params(&settings, ratio).unwrap();
+        let result =
+            reduce_params(&settings, ratio, COLOR_STEPS, LOSSY_STEPS, &DEFAULT_ORDER).unwrap();
         let expected = ((480.0_f64) * ratio.sqrt()).max(MIN_WIDTH as f64) as u32;
         assert_eq!(result.width, expected);
     }
@@ -201,9 +923,11 @@ mod tests {
             width: MIN_WIDTH,
             fps: 15,
             color_index: 0,
+            lossy_index: 0,
         };
         let ratio = 0.7;
-        let result = reduce_params(&settings, ratio).unwrap();
+        let result =
+            reduce_params(&settings, ratio, COLOR_STEPS, LOSSY_STEPS, &DEFAULT_ORDER).unwrap();
         let expected = ((15.0_f64) * ratio).max(MIN_FPS as f64) as u32;
         assert_eq!(result.fps, expected);
     }
@@ -214,30 +938,32 @@ mod tests {
             width: MIN_WIDTH,
             fps: MIN_FPS,
             color_index: 1,
+            lossy_index: 0,
         };
-        let result = reduce_params(&settings, 0.5).unwrap();
+        let result =
+            reduce_params(&settings, 0.5, COLOR_STEPS, LOSSY_STEPS, &DEFAULT_ORDER).unwrap();
         assert_eq!(result.color_index, 2);
     }
 
     #[test]
     fn resolve_color_index_exact_match() {
-        assert_eq!(resolve_color_index(256), 0);
-        assert_eq!(resolve_color_index(128), 1);
-        assert_eq!(resolve_color_index(64), 2);
-        assert_eq!(resolve_color_index(32), 3);
+        assert_eq!(resolve_color_index(COLOR_STEPS, 256), 0);
+        assert_eq!(resolve_color_index(COLOR_STEPS, 128), 1);
+        assert_eq!(resolve_color_index(COLOR_STEPS, 64), 2);
+        assert_eq!(resolve_color_index(COLOR_STEPS, 32), 3);
     }
 
     #[test]
     fn resolve_color_index_rounds_down_to_nearest_step() {
-        assert_eq!(resolve_color_index(200), 1);
-        assert_eq!(resolve_color_index(100), 2);
-        assert_eq!(resolve_color_index(50), 3);
-        assert_eq!(resolve_color_index(33), 3);
+        assert_eq!(resolve_color_index(COLOR_STEPS, 200), 1);
+        assert_eq!(resolve_color_index(COLOR_STEPS, 100), 2);
+        assert_eq!(resolve_color_index(COLOR_STEPS, 50), 3);
+        assert_eq!(resolve_color_index(COLOR_STEPS, 33), 3);
     }
 
     #[test]
     fn resolve_color_index_above_max_gives_first() {
-        assert_eq!(resolve_color_index(512), 0);
+        assert_eq!(resolve_color_index(COLOR_STEPS, 512), 0);
     }
 
     #[test]
@@ -246,8 +972,10 @@ mod tests {
             width: 480,
             fps: 15,
             color_index: 0,
+            lossy_index: 0,
         };
-        let result = reduce_params(&settings, 0.3).unwrap();
+        let result =
+            reduce_params(&settings, 0.3, COLOR_STEPS, LOSSY_STEPS, &DEFAULT_ORDER).unwrap();
         let mut changes = 0;
         if result.width != settings.width {
             changes += 1;
@@ -260,4 +988,240 @@ mod tests {
         }
         assert_eq!(changes, 1);
     }
+
+    #[test]
+    fn apng_starts_lossless_and_quantizes_after() {
+        assert_eq!(resolve_color_index(APNG_COLOR_STEPS, TRUE_COLOR), 0);
+        assert_eq!(resolve_color_index(APNG_COLOR_STEPS, 256), 1);
+
+        let settings = EncodeSettings {
+            width: MIN_WIDTH,
+            fps: MIN_FPS,
+            color_index: 0,
+            lossy_index: 0,
+        };
+        let result = reduce_params(
+            &settings,
+            0.5,
+            APNG_COLOR_STEPS,
+            NO_LOSSY_STEPS,
+            &DEFAULT_ORDER,
+        )
+        .unwrap();
+        assert_eq!(APNG_COLOR_STEPS[result.color_index], 256);
+    }
+
+    #[test]
+    fn apng_ladder_ends_at_smallest_palette() {
+        let settings = EncodeSettings {
+            width: MIN_WIDTH,
+            fps: MIN_FPS,
+            color_index: APNG_COLOR_STEPS.len() - 1,
+            lossy_index: 0,
+        };
+        assert!(
+            reduce_params(
+                &settings,
+                0.5,
+                APNG_COLOR_STEPS,
+                NO_LOSSY_STEPS,
+                &DEFAULT_ORDER
+            )
+            .is_none()
+        );
+    }
+
+    #[test]
+    fn color_steps_follow_format() {
+        assert_eq!(color_steps(OutputFormat::Apng), APNG_COLOR_STEPS);
+        assert_eq!(color_steps(OutputFormat::Gif), COLOR_STEPS);
+        assert_eq!(color_steps(OutputFormat::Mp4), NO_COLOR_STEPS);
+    }
+
+    #[test]
+    fn mp4_ladder_never_reduces_colors() {
+        let encoder = FakeEncoder::new(1.0, 100_000.0);
+        let segment = Segment {
+            start_secs: 0.0,
+            duration_secs: 1.0,
+        };
+        let initial = InitialParams {
+            format: OutputFormat::Mp4,
+            colors: OutputFormat::Mp4.default_colors(),
+            ..gif_initial()
+        };
+        let target = FitTarget {
+            bytes: 1,
+            mode: FitMode::Ladder,
+            tolerance: 0.1,
+        };
+        let output = std::env::temp_dir().join(format!("clippr-mp4-{}.mp4", std::process::id()));
+
+        let result = auto_encode(
+            &encoder,
+            Path::new("input.mp4"),
+            &output,
+            &target,
+            &initial,
+            &segment,
+            &CancelToken::new(),
+            &mut |_| {},
+        );
+        let _ = std::fs::remove_file(&output);
+
+        assert!(matches!(result, Err(Error::TargetUnreachable(3))));
+        assert!(encoder.calls().iter().all(|call| call.colors == TRUE_COLOR));
+    }
+
+    #[test]
+    fn settings_at_spans_floor_to_start() {
+        let start = EncodeSettings {
+            width: 480,
+            fps: 15,
+            color_index: 0,
+            lossy_index: 0,
+        };
+        let floor = floor_settings(&start, COLOR_STEPS, LOSSY_STEPS);
+        assert_eq!(settings_at(&start, &floor, 1.0, &DEFAULT_ORDER), start);
+        assert_eq!(settings_at(&start, &floor, 0.0, &DEFAULT_ORDER), floor);
+        assert_eq!(floor.color_index, COLOR_STEPS.len() - 1);
+    }
+
+    #[test]
+    fn settings_at_reduces_width_before_fps_and_colors() {
+        let start = EncodeSettings {
+            width: 480,
+            fps: 15,
+            color_index: 0,
+            lossy_index: 0,
+        };
+        let floor = floor_settings(&start, COLOR_STEPS, LOSSY_STEPS);
+
+        let upper = settings_at(&start, &floor, 0.8, &DEFAULT_ORDER);
+        assert!(upper.width < 480 && upper.width > MIN_WIDTH);
+        assert_eq!((upper.fps, upper.color_index), (15, 0));
+
+        let middle = settings_at(&start, &floor, 0.6, &DEFAULT_ORDER);
+        assert_eq!(middle.width, MIN_WIDTH);
+        assert!(middle.fps < 15 && middle.fps > MIN_FPS);
+        assert_eq!(middle.color_index, 0);
+    }
+
+    #[test]
+    fn settings_at_turns_lossy_only_below_the_color_floor() {
+        let start = EncodeSettings {
+            width: 480,
+            fps: 15,
+            color_index: 0,
+            lossy_index: 0,
+        };
+        let floor = floor_settings(&start, COLOR_STEPS, LOSSY_STEPS);
+
+        let colors = settings_at(&start, &floor, 0.3, &DEFAULT_ORDER);
+        assert!(colors.color_index > 0);
+        assert_eq!(colors.lossy_index, 0);
+
+        let lossy = settings_at(&start, &floor, 0.1, &DEFAULT_ORDER);
+        assert_eq!(lossy.color_index, COLOR_STEPS.len() - 1);
+        assert_eq!(LOSSY_STEPS[lossy.lossy_index], 40);
+
+        let apng_floor = floor_settings(&start, APNG_COLOR_STEPS, NO_LOSSY_STEPS);
+        let apng = settings_at(
+            &start,
+            &apng_floor,
+            0.1,
+            &active_order(&DEFAULT_ORDER, APNG_COLOR_STEPS, NO_LOSSY_STEPS),
+        );
+        assert_eq!(apng.lossy_index, 0);
+    }
+
+    #[test]
+    fn floor_never_exceeds_start() {
+        let start = EncodeSettings {
+            width: 200,
+            fps: 5,
+            color_index: 0,
+            lossy_index: 0,
+        };
+        let floor = floor_settings(&start, COLOR_STEPS, LOSSY_STEPS);
+        assert_eq!(floor.width, 200);
+        assert_eq!(floor.fps, 5);
+    }
+
+    #[test]
+    fn prediction_keeps_start_params_when_they_fit() {
+        let segment = Segment {
+            start_secs: 0.0,
+            duration_secs: 1.0,
+        };
+        let prediction = predict_ladder(&gif_initial(), &segment, 900, 1000);
+        assert_eq!(prediction.reductions, 0);
+        assert_eq!(prediction.bytes, 900);
+        assert_eq!(prediction.params.width, 480);
+    }
+
+    #[test]
+    fn prediction_follows_the_ladder_of_the_size_model() {
+        let encoder = FakeEncoder::new(1.0, 2000.0);
+        let fitted = fit(&encoder, 1000, FitMode::Ladder).unwrap();
+        let segment = Segment {
+            start_secs: 0.0,
+            duration_secs: 1.0,
+        };
+
+        let prediction = predict_ladder(&gif_initial(), &segment, 2000, 1000);
+
+        assert!(prediction.bytes <= 1000);
+        assert_eq!(prediction.params.width, fitted.params.width);
+        assert_eq!(prediction.params.fps, fitted.params.fps);
+        assert_eq!(prediction.reductions + 1, fitted.attempts);
+    }
+
+    #[test]
+    fn prediction_stops_when_every_axis_is_floored() {
+        let segment = Segment {
+            start_secs: 0.0,
+            duration_secs: 1.0,
+        };
+        let prediction = predict_ladder(&gif_initial(), &segment, 1_000_000, 10);
+        assert!(prediction.bytes > 10);
+        assert_eq!(prediction.params.width, MIN_WIDTH);
+        assert_eq!(prediction.params.fps, MIN_FPS);
+    }
+
+    #[test]
+    fn bisect_lands_within_tolerance_band() {
+        let encoder = FakeEncoder::new(1.0, 10_000.0);
+        let size = run_fit(&encoder, 4000, FitMode::Bisect).unwrap();
+        assert!(size <= 4000);
+        assert!(size >= 3600, "size {size} below tolerance band");
+        assert!(encoder.calls().len() <= MAX_BISECT_ATTEMPTS as usize);
+    }
+
+    #[test]
+    fn bisect_fills_target_at_least_as_well_as_ladder() {
+        let ladder = run_fit(&FakeEncoder::new(1.0, 10_000.0), 4000, FitMode::Ladder).unwrap();
+        let bisect = run_fit(&FakeEncoder::new(1.0, 10_000.0), 4000, FitMode::Bisect).unwrap();
+        assert!(bisect >= ladder);
+    }
+
+    #[test]
+    fn bisect_tries_floor_before_giving_up() {
+        let encoder = FakeEncoder::new(1.0, 1_000_000.0);
+        let result = run_fit(&encoder, 10, FitMode::Bisect);
+        let last = encoder.calls().last().cloned().unwrap();
+        assert!(matches!(result, Err(Error::TargetUnreachable(_))));
+        assert_eq!(
+            (last.width, last.fps, last.colors),
+            (MIN_WIDTH, MIN_FPS, 32)
+        );
+    }
+
+    #[test]
+    fn bisect_returns_immediately_when_start_fits() {
+        let encoder = FakeEncoder::new(1.0, 1000.0);
+        let size = run_fit(&encoder, 2000, FitMode::Bisect).unwrap();
+        assert_eq!(size, 1000);
+        assert_eq!(encoder.calls().len(), 1);
+    }
 }
@@ -0,0 +1,79 @@
+use crate::error::{Error, Result};
+
+pub fn parse_timestamp(raw: &str) -> Result<f64> {
+    let invalid = || {
+        Error::InvalidInput(format!(
+            "'{raw}' is not a timestamp (expected seconds, mm:ss or hh:mm:ss)"
+        ))
+    };
+
+    let fields: Vec<&str> = raw.trim().split(':').collect();
+    if fields.len() > 3 {
+        return Err(invalid());
+    }
+
+    let (seconds_field, unit_fields) = fields.split_last().ok_or_else(invalid)?;
+    let seconds: f64 = match seconds_field.parse() {
+        Ok(seconds)
+            if seconds_field
+                .bytes()
+                .all(|byte| byte.is_ascii_digit() || byte == b'.') =>
+        {
+            seconds
+        }
+        _ => return Err(invalid()),
+    };
+    if !unit_fields.is_empty() && seconds >= 60.0 {
+        return Err(invalid());
+    }
+
+    let mut total = seconds;
+    let mut scale = 60.0;
+    for (position, field) in unit_fields.iter().rev().enumerate() {
+        let value: u32 = field.parse().map_err(|_| invalid())?;
+        let bounded = position + 1 < unit_fields.len();
+        if bounded && value >= 60 {
+            return Err(invalid());
+        }
+        total += value as f64 * scale;
+        scale *= 60.0;
+    }
+
+    Ok(total)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_plain_seconds() {
+        assert_eq!(parse_timestamp("12").unwrap(), 12.0);
+        assert_eq!(parse_timestamp("2.5").unwrap(), 2.5);
+    }
+
+    #[test]
+    fn parse_minutes_and_seconds() {
+        assert_eq!(parse_timestamp("1:23.5").unwrap(), 83.5);
+        assert_eq!(parse_timestamp("90:00").unwrap(), 5400.0);
+    }
+
+    #[test]
+    fn parse_hours_minutes_seconds() {
+        assert_eq!(parse_timestamp("1:02:03").unwrap(), 3723.0);
+        assert_eq!(parse_timestamp("0:00:00.25").unwrap(), 0.25);
+    }
+
+    #[test]
+    fn parse_rejects_out_of_range_fields() {
+        assert!(parse_timestamp("1:60").is_err());
+        assert!(parse_timestamp("1:60:00").is_err());
+    }
+
+    #[test]
+    fn parse_rejects_malformed_input() {
+        for raw in ["", "abc", "-5", "1:", ":30", "1:2:3:4", "1e3", "1:-5"] {
+            assert!(parse_timestamp(raw).is_err(), "accepted {raw:?}");
+        }
+    }
+}
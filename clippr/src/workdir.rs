@@ -0,0 +1,59 @@
+use crate::error::Result;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU32, Ordering};
+
+static NEXT_WORK_DIR: AtomicU32 = AtomicU32::new(0);
+
+pub(crate) struct WorkDir {
+    path: PathBuf,
+}
+
+impl WorkDir {
+    pub(crate) fn create(stem: &Path) -> Result<Self> {
+        let name = format!(
+            ".{}.clippr-{}-{}",
+            stem.file_name().unwrap_or_default().to_string_lossy(),
+            std::process::id(),
+            NEXT_WORK_DIR.fetch_add(1, Ordering::Relaxed),
+        );
+        let path = stem.with_file_name(name);
+        std::fs::create_dir_all(&path)?;
+        Ok(Self { path })
+    }
+
+    pub(crate) fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for WorkDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.path);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn work_dir_is_removed_on_drop() {
+        let stem = std::env::temp_dir().join(format!("clippr-workdir-{}", std::process::id()));
+        let work_dir = WorkDir::create(&stem).unwrap();
+        let path = work_dir.path().to_path_buf();
+        std::fs::write(path.join("chunk_000000.gif"), "partial").unwrap();
+
+        drop(work_dir);
+
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn work_dirs_for_the_same_stem_are_distinct() {
+        let stem = std::env::temp_dir().join(format!("clippr-workdirs-{}", std::process::id()));
+        let first = WorkDir::create(&stem).unwrap();
+        let second = WorkDir::create(&stem).unwrap();
+        assert_ne!(first.path(), second.path());
+        assert_eq!(first.path().parent(), stem.parent());
+    }
+}